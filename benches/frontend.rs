@@ -0,0 +1,62 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rlox::expression::interpreter::Interpreter;
+use rlox::parser::Parser;
+use rlox::scanner::Scanner;
+use rlox::vm::{Compiler, VM};
+
+// 生成一段足够长的合法源码，覆盖数字、字符串、插值、列表和各种运算符
+fn generate_source(lines: usize) -> String {
+    let mut source = String::new();
+    for i in 0..lines {
+        source += &format!(
+            "({i} + 2.5) * [1, 2, {i}][1] - \"s${{{i} ** 2}}\" == nil ? {i} << 2 : -{i}; // line {i}\n"
+        );
+    }
+    source
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let source = generate_source(200);
+    let mut group = c.benchmark_group("frontend");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.bench_function("scan", |b| b.iter(|| Scanner::parse(black_box(&source))));
+    group.finish();
+}
+
+fn bench_scan_parse(c: &mut Criterion) {
+    let source = generate_source(200);
+    let mut group = c.benchmark_group("frontend");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.bench_function("scan+parse", |b| {
+        b.iter(|| Parser::parse(Scanner::parse(black_box(&source))))
+    });
+    group.finish();
+}
+
+// 对比解释器和虚拟机，只计算执行的时间，不包括编译
+fn bench_backends(c: &mut Criterion) {
+    let source =
+        "(1 + 2.5) * [1, 2, 3][1] - 4 ** 2 > 3 ? 7 << 2 : -1 or \"s${1 + 2}\";\n".repeat(200);
+    let program = Parser::parse(Scanner::parse(&source));
+    let chunks: Vec<_> = program.iter().map(Compiler::compile_expression).collect();
+
+    let mut group = c.benchmark_group("backend");
+    group.bench_function("tree-walk", |b| {
+        b.iter(|| {
+            for expr in black_box(&program) {
+                expr.accept(&Interpreter::new()).unwrap();
+            }
+        })
+    });
+    group.bench_function("vm", |b| {
+        b.iter(|| {
+            for chunk in black_box(&chunks) {
+                VM::new().run(chunk).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan, bench_scan_parse, bench_backends);
+criterion_main!(benches);
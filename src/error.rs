@@ -0,0 +1,29 @@
+use crate::expression::interpreter::RuntimeError;
+use crate::parser::ParseError;
+use crate::scanner::ScanError;
+
+// 运行一段 Lox 代码时各个阶段可能产生的错误，由调用方决定如何报告以及退出码
+#[derive(Debug)]
+pub enum LoxError {
+    Scan(Vec<ScanError>),
+    Parse(Vec<ParseError>),
+    Runtime(RuntimeError),
+}
+
+impl From<Vec<ScanError>> for LoxError {
+    fn from(errors: Vec<ScanError>) -> Self {
+        LoxError::Scan(errors)
+    }
+}
+
+impl From<Vec<ParseError>> for LoxError {
+    fn from(errors: Vec<ParseError>) -> Self {
+        LoxError::Parse(errors)
+    }
+}
+
+impl From<RuntimeError> for LoxError {
+    fn from(error: RuntimeError) -> Self {
+        LoxError::Runtime(error)
+    }
+}
@@ -22,7 +22,88 @@ impl ExprVisitor<String> for AstPrinter {
         format!("(group {})", expr.accept(self))
     }
 
+    fn visit_logical(&self, left: &Box<Expression>, op: &Token, right: &Box<Expression>) -> String {
+        format!(
+            "({} {} {})",
+            op.lexeme(),
+            left.accept(self),
+            right.accept(self)
+        )
+    }
+
     fn visit_unary(&self, operator: &Token, right: &Box<Expression>) -> String {
         format!("({} {})", operator.lexeme(), right.accept(self))
     }
+
+    fn visit_ternary(
+        &self,
+        condition: &Box<Expression>,
+        then_expr: &Box<Expression>,
+        else_expr: &Box<Expression>,
+    ) -> String {
+        format!(
+            "(?: {} {} {})",
+            condition.accept(self),
+            then_expr.accept(self),
+            else_expr.accept(self)
+        )
+    }
+
+    fn visit_array(&self, elements: &Vec<Expression>) -> String {
+        let mut out = "(list".to_string();
+        for element in elements {
+            out.push(' ');
+            out.push_str(&element.accept(self));
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_index(
+        &self,
+        object: &Box<Expression>,
+        _bracket: &Token,
+        index: &Box<Expression>,
+    ) -> String {
+        format!("(index {} {})", object.accept(self), index.accept(self))
+    }
+
+    fn visit_index_set(
+        &self,
+        object: &Box<Expression>,
+        _bracket: &Token,
+        index: &Box<Expression>,
+        operator: &Option<Token>,
+        value: &Box<Expression>,
+    ) -> String {
+        let value = match operator {
+            Some(operator) => format!("({}= {})", operator.lexeme(), value.accept(self)),
+            None => value.accept(self),
+        };
+        format!(
+            "(set-index {} {} {})",
+            object.accept(self),
+            index.accept(self),
+            value
+        )
+    }
+
+    fn visit_interpolation(&self, parts: &Vec<Expression>) -> String {
+        let mut out = "(interpolate".to_string();
+        for part in parts {
+            out.push(' ');
+            out.push_str(&part.accept(self));
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_map(&self, _brace: &Token, entries: &Vec<(Expression, Expression)>) -> String {
+        let mut out = "(map".to_string();
+        for (key, value) in entries {
+            out.push_str(&format!(" ({} {})", key.accept(self), value.accept(self)));
+        }
+        out.push(')');
+        out
+    }
 }
@@ -1,24 +1,117 @@
-use crate::expression::{ExprLiteral, ExprVisitor, Expression};
-use crate::reporter::runtime_error;
-use crate::token::{Token, TokenType};
+use crate::expression::{ExprLiteral, ExprVisitor, Expression, MAX_DEPTH};
+use crate::token::{Literal, Token, TokenType};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::ops::{Neg, Not};
+use std::rc::Rc;
+
+// 运行时错误的类别，方便嵌入方按类别处理错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    TypeMismatch,    // 操作数类型不对
+    InvalidOperand,  // 类型正确但是取值不合法，比如非整数的位运算
+    InvalidNumber,   // 字符串无法转换成数字
+    InvalidIndex,    // 下标不是整数
+    IndexOutOfRange, // 下标越界
+    TooDeeplyNested, // 表达式嵌套太深
+    UnknownOperator, // AST 中出现了无法求值的运算符
+    IntegerOverflow, // 整数运算溢出
+    KeyNotFound,     // map 中没有这个 key
+}
 
 #[derive(Debug)]
 pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
     pub msg: &'static str,
     pub token: Token,
 }
 
+impl RuntimeError {
+    pub fn line(&self) -> usize {
+        self.token.line()
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
 type RuntimeResult<T> = Result<T, RuntimeError>;
 
-// 这个跟 ExprLiteral 基本上一样，但是语义不一样，一个表示运行时的值，另一个表示在从源码中解析出来的Token
+// 这个跟 ExprLiteral 基本上一样，但是语义不一样，一个表示运行时的值，另一个表示在从源码中解析出来的Token。
+// Clone 是浅拷贝：list 和 map 拷贝之后仍然共享同一份底层存储（赋值就是别名），需要独立的副本时使用 deep_copy
 #[derive(Debug, Clone)]
 pub enum Value {
-    Str(String), // strings
-    Number(f64), // numbers
-    Nil,         // nil
-    Bool(bool),  // true or false
+    Str(String),                              // strings
+    Int(i64),                                 // 没有小数点的整数
+    Number(f64),                              // 浮点数
+    Nil,                                      // nil
+    Bool(bool),                               // true or false
+    List(Rc<RefCell<Vec<Value>>>),            // lists，赋值时共享同一份底层存储
+    Map(Rc<RefCell<HashMap<MapKey, Value>>>), // maps，与 list 一样共享底层存储
+}
+
+// map 的 key 只能是字符串、数字、bool 或者 nil，list 和 map 这种共享存储的值不能作为 key；
+// 整数值的浮点数会被规范成整数，所以 m[1] 和 m[1.0] 是同一个 key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Str(String),
+    Int(i64),
+    Number(u64), // 非整数的浮点数，按位比较
+    Bool(bool),
+    Nil,
+}
+
+impl MapKey {
+    fn try_from_value(value: Value, token: &Token) -> RuntimeResult<Self> {
+        match value {
+            Value::Str(s) => Ok(MapKey::Str(s)),
+            Value::Int(i) => Ok(MapKey::Int(i)),
+            Value::Number(n) if n.is_nan() => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidIndex,
+                msg: "Map key cannot be NaN",
+                token: token.clone(),
+            }),
+            // 与 == 一致：[-2^63, 2^63) 范围内的整数值才能精确转换成 i64
+            Value::Number(n)
+                if n.fract() == 0_f64 && n >= i64::MIN as f64 && n < i64::MAX as f64 =>
+            {
+                Ok(MapKey::Int(n as i64))
+            }
+            Value::Number(n) => Ok(MapKey::Number(n.to_bits())),
+            Value::Bool(b) => Ok(MapKey::Bool(b)),
+            Value::Nil => Ok(MapKey::Nil),
+            Value::List(_) | Value::Map(_) => Err(RuntimeError {
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Map key must be a string, a number, a bool or nil",
+                token: token.clone(),
+            }),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            MapKey::Str(s) => Value::Str(s.clone()),
+            MapKey::Int(i) => Value::Int(*i),
+            MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            MapKey::Bool(b) => Value::Bool(*b),
+            MapKey::Nil => Value::Nil,
+        }
+    }
+
+    // 输出 map 时按照 key 排序：nil、bool、数字、字符串
+    fn sort_key(&self) -> (u8, f64, &str) {
+        match self {
+            MapKey::Nil => (0, 0_f64, ""),
+            MapKey::Bool(b) => (1, *b as u8 as f64, ""),
+            MapKey::Int(i) => (2, *i as f64, ""),
+            MapKey::Number(bits) => (2, f64::from_bits(*bits), ""),
+            MapKey::Str(s) => (3, 0_f64, s),
+        }
+    }
 }
 
 impl Display for Value {
@@ -27,8 +120,11 @@ impl Display for Value {
             Value::Str(s) => {
                 write!(f, "\"{}\"", s)
             }
+            Value::Int(i) => {
+                write!(f, "{}", i)
+            }
             Value::Number(fl) => {
-                write!(f, "{}", fl)
+                write!(f, "{}", format_number(*fl))
             }
             Value::Nil => {
                 write!(f, "nil")
@@ -39,16 +135,223 @@ impl Display for Value {
             Value::Bool(false) => {
                 write!(f, "false")
             }
+            Value::List(list) => {
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                let map = map.borrow();
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by(|(k1, _), (k2, _)| {
+                    let (s1, n1, t1) = k1.sort_key();
+                    let (s2, n2, t2) = k2.sort_key();
+                    (s1, t1).cmp(&(s2, t2)).then(n1.total_cmp(&n2))
+                });
+
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key.to_value(), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// 精确比较整数和浮点数，不先把整数转换成浮点数，
+// 否则 2^53 + 1 会被舍入成 2^53，导致 == 不满足传递性
+fn cmp_int_number(i: i64, n: f64) -> Option<Ordering> {
+    // i64::MIN as f64 和 i64::MAX as f64 分别是 -2^63 和 2^63，都能精确表示
+    if n.is_nan() {
+        None
+    } else if n >= i64::MAX as f64 {
+        Some(Ordering::Less)
+    } else if n < i64::MIN as f64 {
+        Some(Ordering::Greater)
+    } else {
+        // 整数部分相同时由小数部分决定
+        match i.cmp(&(n.trunc() as i64)) {
+            Ordering::Equal => 0_f64.partial_cmp(&n.fract()),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+// 整数不带小数部分，小数用能精确还原的最短形式，任何大小的数都不使用科学计数法
+pub fn format_number(n: f64) -> String {
+    n.to_string()
+}
+
+impl From<&ExprLiteral> for Value {
+    fn from(literal: &ExprLiteral) -> Self {
+        match literal {
+            ExprLiteral::String(s) => Value::Str(s.clone()),
+            ExprLiteral::Int(i) => Value::Int(*i),
+            ExprLiteral::Number(n) => Value::Number(*n),
+            ExprLiteral::Nil => Value::Nil,
+            ExprLiteral::Bool(b) => Value::Bool(*b),
+        }
+    }
+}
+
+// 列表没有对应的字面量，转换失败时把原来的值还回去
+impl TryFrom<Value> for ExprLiteral {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Str(s) => Ok(ExprLiteral::String(s)),
+            Value::Int(i) => Ok(ExprLiteral::Int(i)),
+            Value::Number(n) => Ok(ExprLiteral::Number(n)),
+            Value::Nil => Ok(ExprLiteral::Nil),
+            Value::Bool(b) => Ok(ExprLiteral::Bool(b)),
+            value @ (Value::List(_) | Value::Map(_)) => Err(value),
+        }
+    }
+}
+
+// 把 Value 转换成 Rust 类型失败时返回的错误，保留原来的值
+#[derive(Debug, Clone)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub value: Value,
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {}, found ", self.expected)?;
+        match &self.value {
+            Value::Nil => write!(f, "nil"),
+            value => write!(f, "{} {}", value.type_name(), value),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+// 整数也可以转换成 f64
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(i) => Ok(i as f64),
+            Value::Number(n) => Ok(n),
+            value => Err(ConversionError {
+                expected: "number",
+                value,
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Str(s) => Ok(s),
+            value => Err(ConversionError {
+                expected: "string",
+                value,
+            }),
+        }
+    }
+}
+
+// 只接受 true/false，不按照真值规则转换
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            value => Err(ConversionError {
+                expected: "bool",
+                value,
+            }),
         }
     }
 }
 
 impl Value {
+    // 递归复制 list 和 map，得到一个与原值完全独立的值。
+    // 原值中同一个 list/map 出现多次（包括循环引用）时只复制一次，拷贝中的共享关系与原值一致
+    pub fn deep_copy(&self) -> Value {
+        self.deep_copy_with(&mut HashMap::new())
+    }
+
+    // copied 记录已经复制过的 list/map，key 是原来的存储地址
+    fn deep_copy_with(&self, copied: &mut HashMap<usize, Value>) -> Value {
+        match self {
+            Value::List(list) => {
+                let key = Rc::as_ptr(list) as usize;
+                if let Some(copy) = copied.get(&key) {
+                    return copy.clone();
+                }
+
+                // 先登记再复制元素，这样循环引用会指向新的 list
+                let copy = Rc::new(RefCell::new(vec![]));
+                copied.insert(key, Value::List(copy.clone()));
+                let elements = list
+                    .borrow()
+                    .iter()
+                    .map(|value| value.deep_copy_with(copied))
+                    .collect();
+                *copy.borrow_mut() = elements;
+                Value::List(copy)
+            }
+            Value::Map(map) => {
+                let key = Rc::as_ptr(map) as usize;
+                if let Some(copy) = copied.get(&key) {
+                    return copy.clone();
+                }
+
+                let copy = Rc::new(RefCell::new(HashMap::new()));
+                copied.insert(key, Value::Map(copy.clone()));
+                let entries = map
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.deep_copy_with(copied)))
+                    .collect();
+                *copy.borrow_mut() = entries;
+                Value::Map(copy)
+            }
+            value => value.clone(),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Int(_) | Value::Number(_) => "number",
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+
     // -val
-    fn negative(self, operator: &Token) -> RuntimeResult<Value> {
+    pub(crate) fn try_neg(self, operator: &Token) -> RuntimeResult<Value> {
         match self {
-            Value::Number(n) => Ok(Value::Number(n.neg())),
+            Value::Int(i) => i.checked_neg().map(Value::Int).ok_or_else(|| RuntimeError {
+                kind: RuntimeErrorKind::IntegerOverflow,
+                msg: "Integer overflow",
+                token: operator.clone(),
+            }),
+            Value::Number(n) => Ok(Value::Number(-n)),
             _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::TypeMismatch,
                 msg: "Cannot apply negative operand on non-numeric values",
                 token: operator.clone(),
             }),
@@ -56,158 +359,461 @@ impl Value {
     }
 
     // !val
-    fn ops_not(self, _operator: &Token) -> RuntimeResult<Value> {
-        Ok(Value::Bool(self.into_bool().not()))
+    pub(crate) fn try_not(self, _operator: &Token) -> RuntimeResult<Value> {
+        Ok(Value::Bool(!self.into_bool()))
     }
 
-    #[allow(clippy::match_like_matches_macro)]
     fn into_bool(self) -> bool {
-        match self {
-            Self::Bool(false) | Self::Nil | Self::Number(0_f64) => false,
-            _ => true,
-        }
+        self.is_truthy()
     }
 
-    fn into_string(self) -> String {
+    pub(crate) fn is_truthy(&self) -> bool {
+        !matches!(
+            self,
+            Self::Bool(false) | Self::Nil | Self::Int(0) | Self::Number(0_f64)
+        )
+    }
+
+    // 与 print 输出的文本相同：字符串不带引号，nil 是空字符串
+    pub fn into_string(self) -> String {
         match self {
             Value::Str(s) => s,
-            Value::Number(n) => n.to_string(),
+            Value::Int(i) => i.to_string(),
+            Value::Number(n) => format_number(n),
             Value::Nil => "".to_string(),
             Value::Bool(true) => "true".to_string(),
             Value::Bool(false) => "false".to_string(),
+            value @ (Value::List(_) | Value::Map(_)) => value.to_string(),
         }
     }
 
-    fn try_into_number(self, operator: &Token) -> RuntimeResult<f64> {
+    // 字符串两边的空白会被忽略；thousands_separators 为 true 时允许 "1,000" 这样的千位分隔符。
+    // 与算术运算一致，bool 和 nil 不会被隐式转换成数字。目前还没有运算用到它
+    #[allow(unused)]
+    fn try_into_number(self, operator: &Token, thousands_separators: bool) -> RuntimeResult<f64> {
         match self {
-            Value::Str(s) => match s.parse::<f64>() {
-                Ok(f) => Ok(f),
-                Err(_) => Err(RuntimeError {
+            Value::Str(s) => match Self::parse_number(&s, thousands_separators) {
+                Some(f) => Ok(f),
+                None => Err(RuntimeError {
+                    kind: RuntimeErrorKind::InvalidNumber,
                     msg: "Error parsing numbers",
                     token: operator.clone(),
                 }),
             },
+            Value::Int(i) => Ok(i as f64),
             Value::Number(n) => Ok(n),
-            Value::Bool(true) => Ok(1_f64),
-            Value::Bool(false) | Value::Nil => Ok(0_f64),
+            Value::Bool(_) | Value::Nil => Err(RuntimeError {
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Cannot convert bool or nil to a number",
+                token: operator.clone(),
+            }),
+            Value::List(_) => Err(RuntimeError {
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Cannot convert a list to a number",
+                token: operator.clone(),
+            }),
+            Value::Map(_) => Err(RuntimeError {
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Cannot convert a map to a number",
+                token: operator.clone(),
+            }),
         }
     }
 
-    // val1 + val2
-    fn add(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
-        match (self, other) {
-            (Value::Str(s1), Value::Str(s2)) => Ok(Value::Str(s1 + s2.as_str())),
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 + n2)),
-            (Value::Str(s), Value::Number(n)) => Ok(Value::Str(s + n.to_string().as_str())), // 语法糖
-            (Value::Number(n), Value::Str(s)) => Ok(Value::Str(n.to_string() + s.as_str())), // 语法糖
+    fn parse_number(s: &str, thousands_separators: bool) -> Option<f64> {
+        let s = s.trim();
+        if thousands_separators && s.contains(',') {
+            s.replace(',', "").parse().ok()
+        } else {
+            s.parse().ok()
+        }
+    }
+
+    // 检查下标是否为范围内的非负整数
+    fn list_index(index: Value, len: usize, bracket: &Token) -> RuntimeResult<usize> {
+        match index {
+            Value::Int(i) => match usize::try_from(i) {
+                Ok(i) if i < len => Ok(i),
+                _ => Err(RuntimeError {
+                    kind: RuntimeErrorKind::IndexOutOfRange,
+                    msg: "Index out of range",
+                    token: bracket.clone(),
+                }),
+            },
+            Value::Number(n) if n.fract() != 0_f64 => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidIndex,
+                msg: "List index must be an integer",
+                token: bracket.clone(),
+            }),
+            Value::Number(n) if n < 0_f64 || n >= len as f64 => Err(RuntimeError {
+                kind: RuntimeErrorKind::IndexOutOfRange,
+                msg: "Index out of range",
+                token: bracket.clone(),
+            }),
+            Value::Number(n) => Ok(n as usize),
             _ => Err(RuntimeError {
-                msg: "Cannot apply addition operand on non-numeric values",
-                token: operator.clone(),
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "List index must be a number",
+                token: bracket.clone(),
             }),
         }
     }
 
-    // val1 - val2
-    fn sub(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
-        match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 - n2)),
+    // val[index]
+    pub(crate) fn index_get(self, index: Value, bracket: &Token) -> RuntimeResult<Value> {
+        match self {
+            Value::List(list) => {
+                let list = list.borrow();
+                let i = Self::list_index(index, list.len(), bracket)?;
+                Ok(list[i].clone())
+            }
+            Value::Map(map) => {
+                let key = MapKey::try_from_value(index, bracket)?;
+                map.borrow().get(&key).cloned().ok_or_else(|| RuntimeError {
+                    kind: RuntimeErrorKind::KeyNotFound,
+                    msg: "Key not found in map",
+                    token: bracket.clone(),
+                })
+            }
             _ => Err(RuntimeError {
-                msg: "Cannot apply subtraction operand on non-numeric values",
-                token: operator.clone(),
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Only lists and maps can be indexed",
+                token: bracket.clone(),
             }),
         }
     }
 
-    // val1 * val2
-    fn mul(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
-        match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 * n2)),
+    // val[index] = new_val
+    pub(crate) fn index_set(
+        self,
+        index: Value,
+        value: Value,
+        bracket: &Token,
+    ) -> RuntimeResult<Value> {
+        match self {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let i = Self::list_index(index, list.len(), bracket)?;
+                list[i] = value.clone();
+                Ok(value)
+            }
+            Value::Map(map) => {
+                let key = MapKey::try_from_value(index, bracket)?;
+                map.borrow_mut().insert(key, value.clone());
+                Ok(value)
+            }
             _ => Err(RuntimeError {
-                msg: "Cannot apply multiplication operand on non-numeric values",
-                token: operator.clone(),
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Only lists and maps can be indexed",
+                token: bracket.clone(),
             }),
         }
     }
 
-    // val1 / val2
-    fn div(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+    // 由交替排列的 key 和 value 构造 map，重复的 key 以后面的为准
+    pub(crate) fn map_from(values: Vec<Value>, brace: &Token) -> RuntimeResult<Value> {
+        let mut map = HashMap::with_capacity(values.len() / 2);
+        let mut values = values.into_iter();
+        while let (Some(key), Some(value)) = (values.next(), values.next()) {
+            map.insert(MapKey::try_from_value(key, brace)?, value);
+        }
+
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
+    }
+
+    // 整数和浮点数都可以参与算术运算，转换成 f64
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    // 两个整数之间使用整数运算，溢出时报错；只要有一个是浮点数就提升为浮点数运算。
+    // 有操作数不是数字时返回 None，由调用者报告类型错误（bool、nil 不会被当作 1 或 0）
+    fn arithmetic(
+        self,
+        other: Self,
+        operator: &Token,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Option<RuntimeResult<Value>> {
         match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 / n2)),
-            _ => Err(RuntimeError {
-                msg: "Cannot apply division operand on non-numeric values",
+            (Value::Int(i1), Value::Int(i2)) => Some(match int_op(i1, i2) {
+                Some(i) => Ok(Value::Int(i)),
+                None => Err(RuntimeError {
+                    kind: RuntimeErrorKind::IntegerOverflow,
+                    msg: "Integer overflow",
+                    token: operator.clone(),
+                }),
+            }),
+            (v1, v2) => Some(Ok(Value::Number(float_op(v1.as_f64()?, v2.as_f64()?)))),
+        }
+    }
+
+    // 严格模式下的 val1 + val2：数字和字符串不能直接拼接，需要用插值 "${n}" 显式转换
+    pub(crate) fn try_add_strict(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        match (&self, &other) {
+            (Value::Str(_), Value::Number(_) | Value::Int(_))
+            | (Value::Number(_) | Value::Int(_), Value::Str(_)) => Err(RuntimeError {
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Cannot add a number and a string in strict mode",
                 token: operator.clone(),
             }),
+            _ => self.try_add(other, operator),
         }
     }
 
-    // val1 > val2
-    fn gt(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+    // val1 + val2
+    pub(crate) fn try_add(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
         match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Bool(n1 > n2)),
+            (Value::Str(s1), Value::Str(s2)) => Ok(Value::Str(s1 + s2.as_str())),
+            (Value::Str(s), n @ (Value::Number(_) | Value::Int(_))) => {
+                Ok(Value::Str(s + n.into_string().as_str())) // 语法糖
+            }
+            (n @ (Value::Number(_) | Value::Int(_)), Value::Str(s)) => {
+                Ok(Value::Str(n.into_string() + s.as_str())) // 语法糖
+            }
+            // 拼接出一个新的列表，两个操作数都不会被修改
+            (Value::List(l1), Value::List(l2)) => {
+                let mut elements = l1.borrow().clone();
+                elements.extend(l2.borrow().iter().cloned());
+                Ok(Value::List(Rc::new(RefCell::new(elements))))
+            }
+            (v1, v2) => v1
+                .arithmetic(v2, operator, i64::checked_add, |n1, n2| n1 + n2)
+                .unwrap_or_else(|| {
+                    Err(RuntimeError {
+                        kind: RuntimeErrorKind::TypeMismatch,
+                        msg: "Cannot apply addition operand on non-numeric values",
+                        token: operator.clone(),
+                    })
+                }),
+        }
+    }
+
+    // val1 - val2
+    pub(crate) fn try_sub(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        self.arithmetic(other, operator, i64::checked_sub, |n1, n2| n1 - n2)
+            .unwrap_or_else(|| {
+                Err(RuntimeError {
+                    kind: RuntimeErrorKind::TypeMismatch,
+                    msg: "Cannot apply subtraction operand on non-numeric values",
+                    token: operator.clone(),
+                })
+            })
+    }
+
+    // val1 * val2
+    pub(crate) fn try_mul(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        self.arithmetic(other, operator, i64::checked_mul, |n1, n2| n1 * n2)
+            .unwrap_or_else(|| {
+                Err(RuntimeError {
+                    kind: RuntimeErrorKind::TypeMismatch,
+                    msg: "Cannot apply multiplication operand on non-numeric values",
+                    token: operator.clone(),
+                })
+            })
+    }
+
+    // val1 / val2
+    // 除法总是得到浮点数（5 / 2 == 2.5），整除的结果打印出来仍然没有小数部分
+    pub(crate) fn try_div(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        match (self.as_f64(), other.as_f64()) {
+            (Some(n1), Some(n2)) => Ok(Value::Number(n1 / n2)),
             _ => Err(RuntimeError {
-                msg: "Cannot apply greater than operand on non-numeric values",
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Cannot apply division operand on non-numeric values",
                 token: operator.clone(),
             }),
         }
     }
 
-    // val1 >= val2
-    fn gte(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+    // val1 ** val2
+    // 整数的非负整数次幂仍然是整数，其他情况都按浮点数计算
+    pub(crate) fn try_pow(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let int_pow = |i1: i64, i2: i64| i1.checked_pow(u32::try_from(i2).ok()?);
         match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Bool(n1 >= n2)),
+            (Value::Int(i1), Value::Int(i2)) if i2 < 0 => {
+                Ok(Value::Number((i1 as f64).powf(i2 as f64)))
+            }
+            (v1, v2) => v1
+                .arithmetic(v2, operator, int_pow, f64::powf)
+                .unwrap_or_else(|| {
+                    Err(RuntimeError {
+                        kind: RuntimeErrorKind::TypeMismatch,
+                        msg: "Cannot apply exponentiation operand on non-numeric values",
+                        token: operator.clone(),
+                    })
+                }),
+        }
+    }
+
+    // 位运算只接受整数，或者值为整数的浮点数
+    fn integer_operands(self, other: Self, operator: &Token) -> RuntimeResult<(i64, i64)> {
+        let integral = |value: &Value| match value {
+            Value::Int(i) => Some(*i),
+            Value::Number(n) if n.fract() == 0_f64 && n.abs() <= i64::MAX as f64 => Some(*n as i64),
+            _ => None,
+        };
+
+        match (&self, &other) {
+            (Value::Int(_) | Value::Number(_), Value::Int(_) | Value::Number(_)) => {
+                match (integral(&self), integral(&other)) {
+                    (Some(i1), Some(i2)) => Ok((i1, i2)),
+                    _ => Err(RuntimeError {
+                        kind: RuntimeErrorKind::InvalidOperand,
+                        msg: "Bitwise operands must be integers",
+                        token: operator.clone(),
+                    }),
+                }
+            }
             _ => Err(RuntimeError {
-                msg: "Cannot apply greater than or equal operand on non-numeric values",
+                kind: RuntimeErrorKind::TypeMismatch,
+                msg: "Cannot apply bitwise operand on non-numeric values",
                 token: operator.clone(),
             }),
         }
     }
 
-    // val1 < val2
-    fn lt(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
-        match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Bool(n1 < n2)),
-            _ => Err(RuntimeError {
-                msg: "Cannot apply less than operand on non-numeric values",
+    // val1 & val2
+    pub(crate) fn try_bit_and(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let (n1, n2) = self.integer_operands(other, operator)?;
+        Ok(Value::Int(n1 & n2))
+    }
+
+    // val1 | val2
+    pub(crate) fn try_bit_or(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let (n1, n2) = self.integer_operands(other, operator)?;
+        Ok(Value::Int(n1 | n2))
+    }
+
+    // val1 ^ val2
+    pub(crate) fn try_bit_xor(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let (n1, n2) = self.integer_operands(other, operator)?;
+        Ok(Value::Int(n1 ^ n2))
+    }
+
+    // val1 << val2
+    pub(crate) fn try_shl(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let (n1, n2) = self.integer_operands(other, operator)?;
+        match u32::try_from(n2).ok().and_then(|n2| n1.checked_shl(n2)) {
+            Some(n) => Ok(Value::Int(n)),
+            None => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidOperand,
+                msg: "Shift amount out of range",
                 token: operator.clone(),
             }),
         }
     }
 
-    // val1 <= val2
-    fn lte(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
-        match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Bool(n1 <= n2)),
-            _ => Err(RuntimeError {
-                msg: "Cannot apply less than or equal operand on non-numeric values",
+    // val1 >> val2
+    pub(crate) fn try_shr(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let (n1, n2) = self.integer_operands(other, operator)?;
+        match u32::try_from(n2).ok().and_then(|n2| n1.checked_shr(n2)) {
+            Some(n) => Ok(Value::Int(n)),
+            None => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidOperand,
+                msg: "Shift amount out of range",
                 token: operator.clone(),
             }),
         }
     }
 
-    // val1 == val2
-    fn eq(self, other: Self, _operator: &Token) -> RuntimeResult<Value> {
+    // 比较两个数字或者两个字符串，和 NaN 比较时返回 None
+    fn compare(self, other: Self, operator: &Token) -> RuntimeResult<Option<Ordering>> {
         match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Bool(n1 == n2)),
-            (Value::Str(s1), Value::Str(s2)) => Ok(Value::Bool(s1 == s2)),
-            (Value::Bool(b1), Value::Bool(b2)) => Ok(Value::Bool(b1 == b2)),
-            (Value::Nil, Value::Nil) => Ok(Value::Bool(true)),
-            _ => Ok(Value::Bool(false)),
+            (Value::Int(i1), Value::Int(i2)) => Ok(Some(i1.cmp(&i2))),
+            (Value::Int(i), Value::Number(n)) => Ok(cmp_int_number(i, n)),
+            (Value::Number(n), Value::Int(i)) => Ok(cmp_int_number(i, n).map(Ordering::reverse)),
+            (Value::Str(s1), Value::Str(s2)) => Ok(Some(s1.cmp(&s2))),
+            (v1, v2) => match (v1.as_f64(), v2.as_f64()) {
+                (Some(n1), Some(n2)) => Ok(n1.partial_cmp(&n2)),
+                _ => Err(RuntimeError {
+                    kind: RuntimeErrorKind::TypeMismatch,
+                    msg: "Operands must be two numbers or two strings",
+                    token: operator.clone(),
+                }),
+            },
         }
     }
 
-    // val1 != val2
-    fn neq(self, other: Self, _operator: &Token) -> RuntimeResult<Value> {
+    // val1 > val2
+    pub(crate) fn try_gt(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let ordering = self.compare(other, operator)?;
+        Ok(Value::Bool(ordering == Some(Ordering::Greater)))
+    }
+
+    // val1 >= val2
+    pub(crate) fn try_gte(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let ordering = self.compare(other, operator)?;
+        Ok(Value::Bool(matches!(
+            ordering,
+            Some(Ordering::Greater | Ordering::Equal)
+        )))
+    }
+
+    // val1 < val2
+    pub(crate) fn try_lt(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let ordering = self.compare(other, operator)?;
+        Ok(Value::Bool(ordering == Some(Ordering::Less)))
+    }
+
+    // val1 <= val2
+    pub(crate) fn try_lte(self, other: Self, operator: &Token) -> RuntimeResult<Value> {
+        let ordering = self.compare(other, operator)?;
+        Ok(Value::Bool(matches!(
+            ordering,
+            Some(Ordering::Less | Ordering::Equal)
+        )))
+    }
+
+    // 任意两个值之间都可以比较是否相等，类型不同时总是不相等；列表比较的是引用是否相同。
+    // 数字遵循 IEEE 754：NaN 和任何值（包括它自己）都不相等，所以 NaN != NaN 为 true，
+    // 与 compare 中 NaN 参与的 <、<=、>、>= 全部为 false 保持一致
+    fn is_equal(&self, other: &Value) -> bool {
         match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Bool(n1 != n2)),
-            (Value::Str(s1), Value::Str(s2)) => Ok(Value::Bool(s1 != s2)),
-            (Value::Bool(b1), Value::Bool(b2)) => Ok(Value::Bool(b1 != b2)),
-            (Value::Nil, Value::Nil) => Ok(Value::Bool(false)),
-            _ => Ok(Value::Bool(true)),
+            (Value::Int(i1), Value::Int(i2)) => i1 == i2,
+            (Value::Number(n1), Value::Number(n2)) => n1 == n2,
+            (Value::Int(i), Value::Number(n)) | (Value::Number(n), Value::Int(i)) => {
+                cmp_int_number(*i, *n) == Some(Ordering::Equal)
+            }
+            (Value::Str(s1), Value::Str(s2)) => s1 == s2,
+            (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
+            (Value::Nil, Value::Nil) => true,
+            (Value::List(l1), Value::List(l2)) => Rc::ptr_eq(l1, l2),
+            (Value::Map(m1), Value::Map(m2)) => Rc::ptr_eq(m1, m2),
+            _ => false,
         }
     }
+
+    // val1 == val2
+    pub(crate) fn try_eq(self, other: Self, _operator: &Token) -> RuntimeResult<Value> {
+        Ok(Value::Bool(self.is_equal(&other)))
+    }
+
+    // val1 != val2
+    pub(crate) fn try_neq(self, other: Self, _operator: &Token) -> RuntimeResult<Value> {
+        Ok(Value::Bool(!self.is_equal(&other)))
+    }
 }
 
-pub struct Interpreter;
+// 求值每个节点之前调用一次（值为 None），求值成功之后再调用一次（带上结果）
+pub type Tracer = Box<dyn FnMut(&Expression, Option<&Value>)>;
+
+pub struct Interpreter {
+    depth: Cell<usize>,
+    max_depth: usize,
+    strict: bool,                   // 严格模式下禁止数字和字符串之间的隐式转换
+    trace: Option<RefCell<Tracer>>, // 调试用的回调，没有设置时只多一次判断
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl ExprVisitor<RuntimeResult<Value>> for Interpreter {
     fn visit_binary(
@@ -218,30 +824,41 @@ impl ExprVisitor<RuntimeResult<Value>> for Interpreter {
     ) -> RuntimeResult<Value> {
         let left_val = self.evaluate(left)?;
         let right_val = self.evaluate(right)?;
+        self.binary(left_val, operator, right_val)
+    }
 
-        match operator.token_type() {
-            // 直接把operator传给了Value的方法，用于报错
-            TokenType::Minus => left_val.sub(right_val, operator),
-            TokenType::Plus => left_val.add(right_val, operator),
-            TokenType::Slash => left_val.div(right_val, operator),
-            TokenType::Star => left_val.mul(right_val, operator),
-            TokenType::Greater => left_val.gt(right_val, operator),
-            TokenType::GreaterEqual => left_val.gte(right_val, operator),
-            TokenType::Less => left_val.lt(right_val, operator),
-            TokenType::LessEqual => left_val.lte(right_val, operator),
-            TokenType::BangEqual => left_val.neq(right_val, operator),
-            TokenType::EqualEqual => left_val.eq(right_val, operator),
-            _ => unreachable!(),
+    // 短路求值，返回决定结果的那个操作数本身，而不是转换后的 bool；
+    // ?? 只在左操作数是 nil 的时候才对右操作数求值
+    fn visit_logical(
+        &self,
+        left: &Box<Expression>,
+        operator: &Token,
+        right: &Box<Expression>,
+    ) -> RuntimeResult<Value> {
+        let left_val = self.evaluate(left)?;
+
+        let short_circuit = match operator.token_type() {
+            TokenType::Or => left_val.is_truthy(),
+            TokenType::And => !left_val.is_truthy(),
+            TokenType::QuestionQuestion => !matches!(left_val, Value::Nil),
+            _ => {
+                return Err(RuntimeError {
+                    kind: RuntimeErrorKind::UnknownOperator,
+                    msg: "Unknown logical operator",
+                    token: operator.clone(),
+                })
+            }
+        };
+
+        if short_circuit {
+            Ok(left_val)
+        } else {
+            self.evaluate(right)
         }
     }
 
     fn visit_literal(&self, value: &ExprLiteral) -> RuntimeResult<Value> {
-        match value {
-            ExprLiteral::String(s) => Ok(Value::Str(s.clone())),
-            ExprLiteral::Number(n) => Ok(Value::Number(*n)),
-            ExprLiteral::Nil => Ok(Value::Nil),
-            ExprLiteral::Bool(b) => Ok(Value::Bool(*b)),
-        }
+        Ok(value.into())
     }
 
     fn visit_grouping(&self, expr: &Box<Expression>) -> RuntimeResult<Value> {
@@ -251,48 +868,245 @@ impl ExprVisitor<RuntimeResult<Value>> for Interpreter {
     fn visit_unary(&self, operator: &Token, right: &Box<Expression>) -> RuntimeResult<Value> {
         let right_val = self.evaluate(right)?;
         match operator.token_type() {
-            TokenType::Minus => right_val.negative(operator),
-            TokenType::Bang => right_val.ops_not(operator),
-            _ => unreachable!(),
+            TokenType::Minus => right_val.try_neg(operator),
+            TokenType::Bang => right_val.try_not(operator),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::UnknownOperator,
+                msg: "Unknown unary operator",
+                token: operator.clone(),
+            }),
         }
     }
+
+    fn visit_ternary(
+        &self,
+        condition: &Box<Expression>,
+        then_expr: &Box<Expression>,
+        else_expr: &Box<Expression>,
+    ) -> RuntimeResult<Value> {
+        // 只对被选中的分支求值
+        if self.evaluate(condition)?.into_bool() {
+            self.evaluate(then_expr)
+        } else {
+            self.evaluate(else_expr)
+        }
+    }
+
+    fn visit_array(&self, elements: &Vec<Expression>) -> RuntimeResult<Value> {
+        let mut values = Vec::with_capacity(elements.len());
+        for element in elements {
+            values.push(self.evaluate(element)?);
+        }
+
+        Ok(Value::List(Rc::new(RefCell::new(values))))
+    }
+
+    fn visit_index(
+        &self,
+        object: &Box<Expression>,
+        bracket: &Token,
+        index: &Box<Expression>,
+    ) -> RuntimeResult<Value> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        object.index_get(index, bracket)
+    }
+
+    fn visit_index_set(
+        &self,
+        object: &Box<Expression>,
+        bracket: &Token,
+        index: &Box<Expression>,
+        operator: &Option<Token>,
+        value: &Box<Expression>,
+    ) -> RuntimeResult<Value> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = match operator {
+            // 复合赋值复用上面求出的 object 和 index 读取旧值
+            Some(operator) => {
+                let current = object.clone().index_get(index.clone(), bracket)?;
+                let value = self.evaluate(value)?;
+                self.binary(current, operator, value)?
+            }
+            None => self.evaluate(value)?,
+        };
+        object.index_set(index, value, bracket)
+    }
+
+    fn visit_map(
+        &self,
+        brace: &Token,
+        entries: &Vec<(Expression, Expression)>,
+    ) -> RuntimeResult<Value> {
+        let mut values = Vec::with_capacity(entries.len() * 2);
+        for (key, value) in entries {
+            values.push(self.evaluate(key)?);
+            values.push(self.evaluate(value)?);
+        }
+
+        Value::map_from(values, brace)
+    }
+
+    fn visit_interpolation(&self, parts: &Vec<Expression>) -> RuntimeResult<Value> {
+        let mut s = String::new();
+        for part in parts {
+            s += &self.evaluate(part)?.into_string();
+        }
+
+        Ok(Value::Str(s))
+    }
 }
 
 impl Interpreter {
-    pub fn interpret(expr: &Expression) {
-        match Interpreter.evaluate(expr) {
-            Ok(val) => {
-                println!("{}", val.into_string());
-            }
-            Err(err) => runtime_error(err),
+    #[inline]
+    pub fn new() -> Self {
+        Interpreter {
+            depth: Cell::new(0),
+            max_depth: MAX_DEPTH,
+            strict: false,
+            trace: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_trace(mut self, trace: impl FnMut(&Expression, Option<&Value>) + 'static) -> Self {
+        self.trace = Some(RefCell::new(Box::new(trace)));
+        self
+    }
+
+    #[inline]
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    #[inline]
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // 依次求值并输出结果，遇到第一个运行时错误时停止并返回这个错误
+    pub fn run(&self, program: &[Expression]) -> RuntimeResult<()> {
+        for expr in program {
+            let val = self.evaluate(expr)?;
+            println!("{}", val.into_string());
+        }
+        Ok(())
+    }
+
+    // 使用默认设置执行程序
+    pub fn interpret(program: &[Expression]) -> RuntimeResult<()> {
+        Interpreter::new().run(program)
+    }
+
+    // 在严格模式下执行程序，给 --strict 使用
+    pub fn interpret_strict(program: &[Expression]) -> RuntimeResult<()> {
+        Interpreter::new().with_strict(true).run(program)
+    }
+
+    // 带有深度检查和 trace 的求值入口，嵌入方应当用它而不是直接调用 accept
+    pub fn evaluate(&self, expr: &Expression) -> RuntimeResult<Value> {
+        if self.depth.get() >= self.max_depth {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::TooDeeplyNested,
+                msg: "Expression too deeply nested.",
+                token: Self::nearest_token(expr),
+            });
+        }
+
+        if let Some(trace) = &self.trace {
+            (trace.borrow_mut())(expr, None);
+        }
+
+        self.depth.set(self.depth.get() + 1);
+        let result = expr.accept(self);
+        self.depth.set(self.depth.get() - 1);
+
+        if let (Some(trace), Ok(value)) = (&self.trace, &result) {
+            (trace.borrow_mut())(expr, Some(value));
+        }
+        result
+    }
+
+    // 二元运算和复合赋值共用
+    fn binary(&self, left_val: Value, operator: &Token, right_val: Value) -> RuntimeResult<Value> {
+        match operator.token_type() {
+            // 直接把operator传给了Value的方法，用于报错
+            TokenType::Minus => left_val.try_sub(right_val, operator),
+            TokenType::Plus if self.strict => left_val.try_add_strict(right_val, operator),
+            TokenType::Plus => left_val.try_add(right_val, operator),
+            TokenType::Slash => left_val.try_div(right_val, operator),
+            TokenType::Star => left_val.try_mul(right_val, operator),
+            TokenType::StarStar => left_val.try_pow(right_val, operator),
+            TokenType::Greater => left_val.try_gt(right_val, operator),
+            TokenType::GreaterEqual => left_val.try_gte(right_val, operator),
+            TokenType::Less => left_val.try_lt(right_val, operator),
+            TokenType::LessEqual => left_val.try_lte(right_val, operator),
+            TokenType::BangEqual => left_val.try_neq(right_val, operator),
+            TokenType::EqualEqual => left_val.try_eq(right_val, operator),
+            TokenType::Ampersand => left_val.try_bit_and(right_val, operator),
+            TokenType::Pipe => left_val.try_bit_or(right_val, operator),
+            TokenType::Caret => left_val.try_bit_xor(right_val, operator),
+            TokenType::LessLess => left_val.try_shl(right_val, operator),
+            TokenType::GreaterGreater => left_val.try_shr(right_val, operator),
+            // parser 不会产生其他运算符，这里报错而不是 panic，方便作为库使用
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::UnknownOperator,
+                msg: "Unknown binary operator",
+                token: operator.clone(),
+            }),
         }
     }
 
-    fn evaluate(&self, expr: &Expression) -> RuntimeResult<Value> {
-        expr.accept(self)
+    // 报错时需要一个 token 来定位，沿着第一个子节点往下找（这里不能再递归了）
+    fn nearest_token(mut expr: &Expression) -> Token {
+        loop {
+            expr = match expr {
+                Expression::Binary { operator, .. }
+                | Expression::Unary { operator, .. }
+                | Expression::Logical { operator, .. } => return operator.clone(),
+                Expression::Index { bracket, .. } | Expression::IndexSet { bracket, .. } => {
+                    return bracket.clone()
+                }
+                Expression::Map { brace, .. } => return brace.clone(),
+                Expression::Grouping { expr } => expr,
+                Expression::Ternary { condition, .. } => condition,
+                Expression::Array { elements } | Expression::Interpolation { parts: elements } => {
+                    match elements.first() {
+                        Some(first) => first,
+                        None => break,
+                    }
+                }
+                Expression::Literal { .. } => break,
+            }
+        }
+
+        Token::new(TokenType::EOF, "", Literal::None, 0, 0)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::expression::interpreter::Interpreter;
+    use crate::expression::interpreter::{
+        format_number, Interpreter, MapKey, RuntimeErrorKind, Value,
+    };
+    use crate::expression::{ExprLiteral, Expression};
     use crate::parser::Parser;
     use crate::scanner::Scanner;
+    use crate::testutil::{eval_err, eval_ok};
+    use crate::token::{Literal, Token, TokenType};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
 
     fn assert_eq(source: &str, expected: &str) {
-        let tokens = Scanner::parse(source);
-        let parser = Parser::parse(tokens);
-        let expr = parser.accept(&Interpreter);
-        assert!(expr.is_ok());
-        let val = expr.unwrap();
-        assert_eq!(val.into_string(), expected);
+        assert_eq!(eval_ok(source).into_string(), expected);
     }
 
     fn assert_error(source: &str) {
-        let tokens = Scanner::parse(source);
-        let parser = Parser::parse(tokens);
-        let expr = parser.accept(&Interpreter);
-        assert!(expr.is_err());
+        eval_err(source);
     }
 
     #[test]
@@ -312,4 +1126,639 @@ mod tests {
 
         assert_error("123 + true == 123123;");
     }
+
+    #[test]
+    fn test_array() {
+        assert_eq("[];", "[]");
+        assert_eq("[1, 2 * 3, \"a\"];", "[1, 6, \"a\"]");
+        assert_eq("[1, [2, [nil]], [], true,];", "[1, [2, [nil]], [], true]");
+    }
+
+    #[test]
+    fn test_list_concatenation() {
+        assert_eq("[1, 2] + [3];", "[1, 2, 3]");
+        assert_eq("[] + [];", "[]");
+        assert_eq("([1] + [[2]])[1][0];", "2");
+        assert_error("[1] + 2;");
+        assert_error("\"a\" + [1];");
+
+        let list = |values: Vec<Value>| Value::List(Rc::new(RefCell::new(values)));
+        let plus = Token::new(TokenType::Plus, "+", Literal::None, 1, 0);
+        let (a, b) = (list(vec![Value::Number(1_f64)]), list(vec![Value::Nil]));
+        let sum = a.clone().try_add(b.clone(), &plus).unwrap();
+
+        assert_eq!(sum.to_string(), "[1, nil]");
+        assert_eq!(a.to_string(), "[1]");
+        assert_eq!(b.to_string(), "[nil]");
+    }
+
+    #[test]
+    fn test_index() {
+        assert_eq("[1, 2, 3][1];", "2");
+        assert_eq("[[1, 2], [3]][0][1];", "2");
+        assert_eq("[1, 2, 3][0] = 9;", "9");
+
+        assert_error("[1, 2, 3][3];");
+        assert_error("[1, 2, 3][-1];");
+        assert_error("[1, 2, 3][0.5];");
+        assert_error("[1, 2, 3][\"0\"];");
+        assert_error("[1, 2, 3][3] = 9;");
+        assert_error("1[0];");
+    }
+
+    #[test]
+    fn test_map() {
+        assert_eq("{};", "{}");
+        assert_eq(
+            "{\"b\": 2, \"a\": [1], 1.5: nil, 1: true};",
+            "{1: true, 1.5: nil, \"a\": [1], \"b\": 2}",
+        );
+        assert_eq("{\"a\": 1, \"a\": 2}[\"a\"];", "2");
+        assert_eq("{\"a\": {\"b\": 3}}[\"a\"][\"b\"];", "3");
+        // 整数值的浮点数和整数是同一个 key
+        assert_eq("{1: \"x\"}[1.0];", "x");
+        assert_eq("{1: 2}[1] = 3;", "3");
+        assert_eq("{\"n\": 1}[\"n\"] += 2;", "3");
+
+        let program = Parser::parse(Scanner::parse("{\"a\": 1}[\"b\"];"));
+        let err = program[0].accept(&Interpreter::new()).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::KeyNotFound);
+        assert_error("{[1]: 2};");
+        assert_error("{}[nil];");
+        assert_error("{}[0 / 0] = 1;");
+    }
+
+    #[test]
+    fn test_map_keys() {
+        assert_eq("{2: \"a\", 2.5: \"b\", \"2\": \"c\"}[2.5];", "b");
+        assert_eq("{2: \"a\", 2.5: \"b\", \"2\": \"c\"}[\"2\"];", "c");
+        assert_eq("{true: 1, false: 2}[1 < 2];", "1");
+        assert_eq("{nil: 1}[nil];", "1");
+        assert_eq("{}[false] = 1;", "1");
+        assert_eq(
+            "{\"s\": 1, 2: 2, true: 3, nil: 4, false: 5};",
+            "{nil: 4, false: 5, true: 3, 2: 2, \"s\": 1}",
+        );
+
+        for source in ["{[1]: 2};", "{}[{}];", "{}[[]] = 1;"] {
+            let err = eval_err(source);
+            assert_eq!(err.kind, RuntimeErrorKind::TypeMismatch);
+            assert_eq!(err.msg, "Map key must be a string, a number, a bool or nil");
+        }
+    }
+
+    #[test]
+    fn test_map_is_shared() {
+        let brace = Token::new(TokenType::LeftBrace, "{", Literal::None, 1, 0);
+        let map = Value::map_from(vec![Value::Str("a".into()), Value::Int(1)], &brace).unwrap();
+        map.clone()
+            .index_set(Value::Str("b".into()), Value::Int(2), &brace)
+            .unwrap();
+
+        assert_eq!(map.to_string(), "{\"a\": 1, \"b\": 2}");
+        assert!(map.is_equal(&map.clone()));
+        assert_eq!(map.type_name(), "map");
+    }
+
+    #[test]
+    fn test_index_set_shares_list() {
+        let bracket = Token::new(TokenType::RightBracket, "]", Literal::None, 1, 0);
+        let list = Rc::new(RefCell::new(vec![Value::Number(1_f64)]));
+
+        let val = Value::List(list.clone())
+            .index_set(Value::Number(0_f64), Value::Number(9_f64), &bracket)
+            .unwrap();
+        assert_eq!(val.into_string(), "9");
+        assert_eq!(Value::List(list).into_string(), "[9]");
+    }
+
+    #[test]
+    fn test_equality() {
+        assert_eq("1 == \"1\";", "false");
+        assert_eq("1 != \"1\";", "true");
+        assert_eq("nil == nil;", "true");
+        assert_eq("nil == false;", "false");
+        assert_eq("\"a\" + \"b\" == \"ab\";", "true");
+        assert_eq("[1] == [1];", "false");
+
+        let list = Value::List(Rc::new(RefCell::new(vec![])));
+        assert!(list.is_equal(&list.clone()));
+    }
+
+    #[test]
+    fn test_deep_copy() {
+        let inner = Rc::new(RefCell::new(vec![Value::Int(1)]));
+        let list = Value::List(Rc::new(RefCell::new(vec![
+            Value::List(inner.clone()),
+            Value::List(inner.clone()),
+        ])));
+
+        // clone 只是别名，修改会互相影响
+        let alias = list.clone();
+        inner.borrow_mut().push(Value::Int(2));
+        assert_eq!(alias.clone().into_string(), "[[1, 2], [1, 2]]");
+        assert!(alias.is_equal(&list));
+
+        // deep_copy 之后互不影响，但拷贝内部的共享关系保持不变
+        let copy = list.deep_copy();
+        inner.borrow_mut().push(Value::Int(3));
+        assert_eq!(list.clone().into_string(), "[[1, 2, 3], [1, 2, 3]]");
+        assert_eq!(copy.clone().into_string(), "[[1, 2], [1, 2]]");
+        assert!(!copy.is_equal(&list));
+        let Value::List(copied) = &copy else {
+            panic!("expected a list");
+        };
+        let (Value::List(first), Value::List(second)) = (&copied.borrow()[0], &copied.borrow()[1])
+        else {
+            panic!("expected nested lists");
+        };
+        assert!(Rc::ptr_eq(first, second));
+
+        // 循环引用不会导致无限递归
+        let map = Rc::new(RefCell::new(HashMap::new()));
+        map.borrow_mut()
+            .insert(MapKey::Str("self".to_string()), Value::Map(map.clone()));
+        let Value::Map(copy) = Value::Map(map.clone()).deep_copy() else {
+            panic!("expected a map");
+        };
+        let this = copy.borrow()[&MapKey::Str("self".to_string())].clone();
+        assert!(matches!(this, Value::Map(m) if Rc::ptr_eq(&m, &copy)));
+        // 打破循环引用，避免测试中的内存泄漏
+        map.borrow_mut().clear();
+        copy.borrow_mut().clear();
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eq("[1][0] += 2;", "3");
+        assert_eq("[5][0] -= 2;", "3");
+        assert_eq("[2][0] *= 4;", "8");
+        assert_eq("[8][0] /= 2;", "4");
+        assert_eq("[\"a\"][0] += \"b\";", "ab");
+
+        assert_error("[nil][0] += 1;");
+
+        // 下标表达式和被赋值的对象都只求值一次
+        let lists = Rc::new(RefCell::new(0));
+        let count = lists.clone();
+        let interpreter = Interpreter::new().with_trace(move |expr, value| {
+            if value.is_none() && matches!(expr, Expression::Array { .. }) {
+                *count.borrow_mut() += 1;
+            }
+        });
+        let program = Parser::parse(Scanner::parse("[1][[0][0]] += 2;"));
+        assert_eq!(interpreter.evaluate(&program[0]).unwrap().to_string(), "3");
+        assert_eq!(*lists.borrow(), 2);
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_eq("\"Hello, ${\"world\"}!\";", "Hello, world!");
+        assert_eq("\"${1} + ${2} = ${1 + 2}\";", "1 + 2 = 3");
+        assert_eq("\"list: ${[1, \"${2}\"]}\";", "list: [1, \"2\"]");
+        assert_eq("\"cost: \\${1 + 1}\";", "cost: ${1 + 1}");
+
+        assert_error("\"${-nil}\";");
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq("2 ** 10;", "1024");
+        assert_eq("2 ** 3 ** 2 == 512;", "true");
+        assert_eq("2 * 3 ** 2;", "18");
+        assert_eq("4 ** 0.5;", "2");
+
+        assert_error("2 ** \"3\";");
+    }
+
+    #[test]
+    fn test_bitwise() {
+        assert_eq("6 & 3;", "2");
+        assert_eq("6 | 3;", "7");
+        assert_eq("6 ^ 3;", "5");
+        assert_eq("1 << 4;", "16");
+        assert_eq("-16 >> 2;", "-4");
+        assert_eq("1 | 2 & 3 == 3;", "true");
+        assert_eq("4.0 & 5;", "4");
+
+        assert_error("1.5 & 2;");
+        assert_error("1 | \"2\";");
+        assert_error("1 << 64;");
+        assert_error("1 >> -1;");
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let eval = |source: &str, strict: bool| {
+            let program = Parser::parse(Scanner::parse(source));
+            Interpreter::new().with_strict(strict).evaluate(&program[0])
+        };
+
+        assert_eq!(eval("1 + \"x\";", false).unwrap().into_string(), "1x");
+        assert_eq!(eval("\"x\" + 1.5;", false).unwrap().into_string(), "x1.5");
+
+        for source in ["1 + \"x\";", "\"x\" + 1.5;", "[\"a\"][0] += 1;"] {
+            let err = eval(source, true).unwrap_err();
+            assert_eq!(err.kind, RuntimeErrorKind::TypeMismatch);
+            assert_eq!(err.msg, "Cannot add a number and a string in strict mode");
+        }
+
+        // 其他加法以及显式的插值不受影响
+        assert_eq!(eval("1 + 2.5;", true).unwrap().into_string(), "3.5");
+        assert_eq!(eval("\"a\" + \"b\";", true).unwrap().into_string(), "ab");
+        assert_eq!(eval("[1] + [2];", true).unwrap().into_string(), "[1, 2]");
+        assert_eq!(eval("\"x${1}\";", true).unwrap().into_string(), "x1");
+    }
+
+    #[test]
+    fn test_nan_semantics() {
+        // NaN 遵循 IEEE 754，和任何值都不相等，也没有大小关系
+        assert_eq("0.0 / 0.0 == 0.0 / 0.0;", "false");
+        assert_eq("0.0 / 0.0 != 0.0 / 0.0;", "true");
+        assert_eq("0.0 / 0.0 == 1;", "false");
+        for op in ["<", "<=", ">", ">="] {
+            assert_eq(&format!("0.0 / 0.0 {op} 1;"), "false");
+            assert_eq(&format!("1 {op} 0.0 / 0.0;"), "false");
+            assert_eq(&format!("0.0 / 0.0 {op} 0.0 / 0.0;"), "false");
+        }
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        assert_eq("\"apple\" < \"banana\";", "true");
+        assert_eq("\"apple\" > \"banana\";", "false");
+        assert_eq("\"app\" <= \"apple\";", "true");
+        assert_eq("\"b\" >= \"abc\";", "true");
+        assert_eq("\"Z\" < \"a\";", "true");
+        assert_eq("\"\" < \"a\";", "true");
+
+        assert_error("\"1\" < 2;");
+        assert_error("1 >= \"0\";");
+        assert_error("[1] < [2];");
+    }
+
+    #[test]
+    fn test_try_methods() {
+        let op = |token_type: TokenType, lexeme: &str| {
+            Token::new(token_type, lexeme, Literal::None, 1, 0)
+        };
+        let num = Value::Number;
+        let string = |s: &str| Value::Str(s.to_string());
+
+        let eval = |result: Result<Value, _>| result.unwrap().into_string();
+        assert_eq!(
+            eval(num(1.0).try_add(num(2.0), &op(TokenType::Plus, "+"))),
+            "3"
+        );
+        assert_eq!(
+            eval(string("a").try_add(num(1.0), &op(TokenType::Plus, "+"))),
+            "a1"
+        );
+        assert_eq!(
+            eval(num(5.0).try_sub(num(7.0), &op(TokenType::Minus, "-"))),
+            "-2"
+        );
+        assert_eq!(
+            eval(num(3.0).try_mul(num(4.0), &op(TokenType::Star, "*"))),
+            "12"
+        );
+        assert_eq!(
+            eval(num(3.0).try_div(num(4.0), &op(TokenType::Slash, "/"))),
+            "0.75"
+        );
+        assert_eq!(
+            eval(num(2.0).try_pow(num(3.0), &op(TokenType::StarStar, "**"))),
+            "8"
+        );
+        assert_eq!(eval(num(2.0).try_neg(&op(TokenType::Minus, "-"))), "-2");
+        assert_eq!(eval(Value::Nil.try_not(&op(TokenType::Bang, "!"))), "true");
+        assert_eq!(
+            eval(num(6.0).try_bit_and(num(3.0), &op(TokenType::Ampersand, "&"))),
+            "2"
+        );
+        assert_eq!(
+            eval(num(1.0).try_shl(num(4.0), &op(TokenType::LessLess, "<<"))),
+            "16"
+        );
+        assert_eq!(
+            eval(string("a").try_lt(string("b"), &op(TokenType::Less, "<"))),
+            "true"
+        );
+        assert_eq!(
+            eval(num(1.0).try_eq(string("1"), &op(TokenType::EqualEqual, "=="))),
+            "false"
+        );
+
+        assert!(string("a").try_neg(&op(TokenType::Minus, "-")).is_err());
+        assert!(Value::Nil
+            .try_mul(num(1.0), &op(TokenType::Star, "*"))
+            .is_err());
+        assert!(num(1.5)
+            .try_bit_or(num(1.0), &op(TokenType::Pipe, "|"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_nil_coalescing() {
+        assert_eq("nil ?? 5;", "5");
+        assert_eq("false ?? 5;", "false");
+        assert_eq("0 ?? 5;", "0");
+        assert_eq("nil ?? nil ?? \"x\";", "x");
+        // 左操作数不是 nil 时不会对右操作数求值
+        assert_eq("1 ?? -nil;", "1");
+        assert_error("nil ?? -nil;");
+    }
+
+    #[test]
+    fn test_logical() {
+        assert_eq("nil or 5;", "5");
+        assert_eq("\"a\" and \"b\";", "b");
+        assert_eq("1 and 2;", "2");
+        assert_eq("(false or nil) == nil;", "true");
+        assert_eq("(nil and -\"x\") == nil;", "true");
+        assert_eq("\"default\" or -\"x\";", "default");
+        assert_eq("[1] or 2;", "[1]");
+
+        // 保留操作数原本的类型
+        let program = Parser::parse(Scanner::parse("nil or 5;"));
+        assert!(matches!(
+            program[0].accept(&Interpreter::new()),
+            Ok(Value::Int(5))
+        ));
+        let program = Parser::parse(Scanner::parse("\"a\" and \"b\";"));
+        assert!(matches!(
+            program[0].accept(&Interpreter::new()),
+            Ok(Value::Str(s)) if s == "b"
+        ));
+    }
+
+    #[test]
+    fn test_ternary() {
+        assert_eq("true ? 1 : 2;", "1");
+        assert_eq("false ? 1 : 2;", "2");
+        assert_eq("nil ? 1 : 2 > 1 ? \"a\" : \"b\";", "a");
+        assert_eq("false ? 1 : false ? 2 : 3;", "3");
+        // 未被选中的分支不会被求值
+        assert_eq("true ? 1 : -\"abc\";", "1");
+    }
+
+    #[test]
+    fn test_too_deeply_nested() {
+        // 手动构造 parser 不会产生的深层嵌套
+        let mut expr = Expression::Literal {
+            value: ExprLiteral::Number(1_f64),
+        };
+        for _ in 0..10000 {
+            expr = Expression::Grouping {
+                expr: Box::new(expr),
+            };
+        }
+        let err = expr.accept(&Interpreter::new()).unwrap_err();
+        assert_eq!(err.msg, "Expression too deeply nested.");
+
+        let program = Parser::parse(Scanner::parse("-(-(1));"));
+        assert!(program[0]
+            .accept(&Interpreter::new().with_max_depth(3))
+            .is_err());
+        assert!(program[0]
+            .accept(&Interpreter::new().with_max_depth(4))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_literal_conversions() {
+        let literals = [
+            ExprLiteral::String("abc".to_string()),
+            ExprLiteral::Int(-7),
+            ExprLiteral::Number(1.5),
+            ExprLiteral::Nil,
+            ExprLiteral::Bool(true),
+            ExprLiteral::Bool(false),
+        ];
+        for literal in literals {
+            let value = Value::from(&literal);
+            assert_eq!(ExprLiteral::try_from(value).unwrap(), literal);
+        }
+
+        let list = Value::List(Rc::new(RefCell::new(vec![Value::Nil])));
+        assert!(ExprLiteral::try_from(list).is_err());
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let error = |source: &str| {
+            let program = Parser::parse(Scanner::parse(source));
+            program[0].accept(&Interpreter::new()).unwrap_err()
+        };
+
+        let err = error("1 +\n  -\"abc\";");
+        assert_eq!(err.kind, RuntimeErrorKind::TypeMismatch);
+        assert_eq!(err.line(), 2);
+        assert_eq!(
+            err.to_string(),
+            "Cannot apply negative operand on non-numeric values"
+        );
+
+        assert_eq!(error("[1][1];").kind, RuntimeErrorKind::IndexOutOfRange);
+        assert_eq!(error("[1][0.5];").kind, RuntimeErrorKind::InvalidIndex);
+        assert_eq!(error("1.5 | 1;").kind, RuntimeErrorKind::InvalidOperand);
+    }
+
+    #[test]
+    fn test_unknown_operator() {
+        let number = |n: f64| {
+            Box::new(Expression::Literal {
+                value: ExprLiteral::Number(n),
+            })
+        };
+        let comma = Token::new(TokenType::Comma, ",", Literal::None, 3, 0);
+
+        let binary = Expression::Binary {
+            left: number(1_f64),
+            operator: comma.clone(),
+            right: number(2_f64),
+        };
+        let err = binary.accept(&Interpreter::new()).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::UnknownOperator);
+        assert_eq!(err.line(), 3);
+        assert_eq!(err.token.lexeme(), ",");
+
+        let unary = Expression::Unary {
+            operator: comma.clone(),
+            right: number(1_f64),
+        };
+        let err = unary.accept(&Interpreter::new()).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::UnknownOperator);
+
+        // 不会被当成 and 或者 or 处理
+        let logical = Expression::Logical {
+            left: number(0_f64),
+            operator: comma,
+            right: number(2_f64),
+        };
+        let err = logical.accept(&Interpreter::new()).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::UnknownOperator);
+        assert_eq!(err.msg, "Unknown logical operator");
+    }
+
+    #[test]
+    fn test_integers() {
+        let evaluate = |source: &str| {
+            let program = Parser::parse(Scanner::parse(source));
+            program[0].accept(&Interpreter::new())
+        };
+
+        // 整数之间的运算保持为整数
+        assert!(matches!(evaluate("1 + 2 * 3;"), Ok(Value::Int(7))));
+        assert!(matches!(
+            evaluate("-(2 ** 62);"),
+            Ok(Value::Int(-4611686018427387904))
+        ));
+        assert!(matches!(evaluate("7 & 3;"), Ok(Value::Int(3))));
+        assert_eq("9007199254740993 + 0;", "9007199254740993");
+
+        // 和浮点数混合时提升为浮点数
+        assert!(matches!(evaluate("1 + 2.5;"), Ok(Value::Number(n)) if n == 3.5));
+        assert!(matches!(evaluate("2.0 * 3;"), Ok(Value::Number(n)) if n == 6_f64));
+        assert!(matches!(evaluate("2 ** -1;"), Ok(Value::Number(n)) if n == 0.5));
+        assert_eq("1 == 1.0;", "true");
+        assert_eq("2 < 2.5;", "true");
+
+        // 除法总是得到浮点数
+        assert!(matches!(evaluate("5 / 2;"), Ok(Value::Number(n)) if n == 2.5));
+        assert!(matches!(evaluate("6 / 2;"), Ok(Value::Number(n)) if n == 3_f64));
+        assert_eq("6 / 2;", "3");
+
+        let err = evaluate("9223372036854775807 + 1;").unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::IntegerOverflow);
+        assert_error("2 ** 64;");
+    }
+
+    #[test]
+    fn test_int_number_comparison() {
+        // 2^53 + 1 不能用浮点数表示，与 2^53 的浮点数不相等
+        assert_eq("9007199254740993 == 9007199254740992.0;", "false");
+        assert_eq("9007199254740993 > 9007199254740992.0;", "true");
+        assert_eq("9007199254740992.0 < 9007199254740993;", "true");
+        assert_eq("9007199254740992 == 9007199254740992.0;", "true");
+        assert_eq("9223372036854775807 < 9223372036854775807.0;", "true");
+        assert_eq("-2 > -2.5;", "true");
+        assert_eq("3 <= 2.5;", "false");
+        assert_eq("1 == 0.0 / 0;", "false");
+
+        // map 的 key 同样按精确的值比较
+        let err = eval_err("{9007199254740993: 1}[9007199254740992.0];");
+        assert_eq!(err.kind, RuntimeErrorKind::KeyNotFound);
+        assert_eq("{9007199254740992: 1}[9007199254740992.0];", "1");
+        assert_eq(
+            "{-9223372036854775807 - 1: 1}[-9223372036854775808.0];",
+            "1",
+        );
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(3.0), "3");
+        assert_eq!(format_number(2.5), "2.5");
+        assert_eq!(format_number(-0.125), "-0.125");
+        assert_eq!(format_number(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_number(9007199254740992.0), "9007199254740992");
+        assert_eq!(format_number(1e20), "100000000000000000000");
+        assert_eq!(format_number(1e21), "1000000000000000000000");
+        assert_eq!(format_number(-1.5e25), "-15000000000000000000000000");
+        assert_eq!(format_number(1e-7), "0.0000001");
+
+        assert_eq("6 / 2;", "3");
+        assert_eq("10 / 4;", "2.5");
+        assert_eq("\"n = \" + 4 / 2;", "n = 2");
+    }
+
+    #[test]
+    fn test_try_into_number() {
+        let token = Token::new(TokenType::Plus, "+", Literal::None, 1, 0);
+        let to_number = |s: &str, thousands_separators: bool| {
+            Value::Str(s.to_string()).try_into_number(&token, thousands_separators)
+        };
+
+        assert_eq!(to_number(" 42 ", false).unwrap(), 42_f64);
+        assert_eq!(to_number("\t1.5\n", false).unwrap(), 1.5);
+        let err = to_number("1,000", false).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::InvalidNumber);
+
+        assert_eq!(to_number(" 1,000 ", true).unwrap(), 1000_f64);
+        assert_eq!(to_number("1,234,567.5", true).unwrap(), 1234567.5);
+        let err = to_number("1,0a0", true).unwrap_err();
+        assert_eq!(err.msg, "Error parsing numbers");
+    }
+
+    #[test]
+    fn test_no_implicit_coercion() {
+        // 算术运算和数字转换都不会把 bool、nil 当作数字
+        for source in [
+            "true + 1;",
+            "nil + 1;",
+            "1 - false;",
+            "nil * 2;",
+            "true / 1;",
+            "-nil;",
+        ] {
+            let program = Parser::parse(Scanner::parse(source));
+            let err = program[0].accept(&Interpreter::new()).unwrap_err();
+            assert_eq!(err.kind, RuntimeErrorKind::TypeMismatch, "{source}");
+        }
+
+        let token = Token::new(TokenType::Plus, "+", Literal::None, 1, 0);
+        for value in [Value::Bool(true), Value::Bool(false), Value::Nil] {
+            let err = value.try_into_number(&token, false).unwrap_err();
+            assert_eq!(err.kind, RuntimeErrorKind::TypeMismatch);
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let log = calls.clone();
+        let interpreter = Interpreter::new().with_trace(move |expr, value| {
+            let value = value.map(|v| v.to_string());
+            log.borrow_mut().push((expr.to_string(), value));
+        });
+
+        let program = Parser::parse(Scanner::parse("1 + 2 * 3;"));
+        assert_eq!(interpreter.evaluate(&program[0]).unwrap().to_string(), "7");
+
+        // 5 个节点，每个节点求值前后各一次
+        let calls = calls.borrow();
+        assert_eq!(calls.len(), 10);
+        assert_eq!(calls[0], ("(+ 1 (* 2 3))".to_string(), None));
+        assert_eq!(calls[1], ("1".to_string(), None));
+        assert_eq!(calls[2], ("1".to_string(), Some("1".to_string())));
+        assert_eq!(
+            calls[9],
+            ("(+ 1 (* 2 3))".to_string(), Some("7".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_try_from_value() {
+        let eval = |source: &str| {
+            let program = Parser::parse(Scanner::parse(source));
+            Interpreter::new().evaluate(&program[0]).unwrap()
+        };
+
+        assert_eq!(f64::try_from(eval("1 + 2;")).unwrap(), 3_f64);
+        assert_eq!(f64::try_from(eval("7 / 2;")).unwrap(), 3.5);
+        assert_eq!(String::try_from(eval("\"a\" + \"b\";")).unwrap(), "ab");
+        assert!(bool::try_from(eval("1 < 2;")).unwrap());
+
+        let err = f64::try_from(eval("\"1\";")).unwrap_err();
+        assert_eq!(err.to_string(), "expected number, found string \"1\"");
+        let err = String::try_from(eval("[1];")).unwrap_err();
+        assert_eq!(err.to_string(), "expected string, found list [1]");
+        let err = bool::try_from(eval("nil;")).unwrap_err();
+        assert_eq!(err.to_string(), "expected bool, found nil");
+        assert!(matches!(
+            bool::try_from(eval("1;")).unwrap_err().value,
+            Value::Int(1)
+        ));
+    }
 }
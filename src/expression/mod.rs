@@ -1,13 +1,36 @@
+use crate::expression::ast_printer::AstPrinter;
 use crate::token::Token;
 use std::fmt::{Debug, Display, Formatter};
 
+// parser 和解释器共用的最大嵌套深度，超过之后报告 "Expression too deeply nested."。
+// 递归时通过 grow_stack 按需扩展栈，所以在这个深度以内不会栈溢出（debug 构建、测试线程的小栈也一样）
+pub const MAX_DEPTH: usize = 1000;
+
+// 剩余的栈空间少于 RED_ZONE 时，为接下来的递归分配一段 STACK_SEGMENT 大小的新栈。
+// debug 构建中 parser 每一层嵌套大约需要 40KB 的栈，RED_ZONE 要比这个大
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+#[inline]
+pub(crate) fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+}
+
 /*
  * Lox语法规则：
- * expression     → literal | unary | binary| grouping ;
+ * expression     → literal | unary | binary | grouping | ternary | array
+ *                | index | index_set | interpolation | logical | map ;
  * literal        → NUMBER | STRING | "true" | "false" | "nil" ;
  * grouping       → "(" expression ")" ;
  * unary          → ( "-" | "!" ) expression ;
  * binary         → expression operator expression ;
+ * ternary        → expression "?" expression ":" expression ;
+ * array          → "[" ( expression ( "," expression )* )? "]" ;
+ * index          → expression "[" expression "]" ;
+ * index_set      → expression "[" expression "]" ( "=" | "+=" | "-=" | "*=" | "/=" ) expression ;
+ * interpolation  → STRING 中通过 "${" expression "}" 嵌入的表达式 ;
+ * logical        → expression ( "and" | "or" | "??" ) expression ;
+ * map            → "{" ( expression ":" expression ( "," expression ":" expression )* )? "}" ;
  * operator       → "==" | "!=" | "<" | "<=" | ">" | ">="| "+"  | "-"  | "*" | "/" ;
  */
 // 定义AST的宏（支持你期望的语法）
@@ -18,7 +41,8 @@ macro_rules! define_ast {
         ),+
     ) => {
         // AST节点枚举定义
-        #[derive(Debug)]
+        #[derive(Debug, Clone, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum Expression {
             $(
                 $node {
@@ -28,22 +52,23 @@ macro_rules! define_ast {
         }
 
         // Visitor trait定义
+        #[allow(clippy::ptr_arg)]
         pub trait ExprVisitor<T> {
             $(
                 fn $visitor(&self, $($param: &$type),*) -> T;
             )+
         }
 
-        // 实现accept方法
+        // 实现accept方法，所有 visitor 的递归都经过这里，所以在这里按需扩展栈
         impl Expression {
             pub fn accept<V: ExprVisitor<T>, T>(&self, visitor: &V) -> T {
-                match self {
+                grow_stack(|| match self {
                     $(
                         Expression::$node { $($param),* } => {
                             visitor.$visitor($($param),*)
                         }
                     ),+
-                }
+                })
             }
         }
     };
@@ -53,12 +78,115 @@ define_ast! {
     (Binary(left: Box<Expression>, operator: Token, right: Box<Expression>), visit_binary),
     (Literal(value: ExprLiteral), visit_literal),
     (Grouping(expr: Box<Expression>), visit_grouping),
-    (Unary(operator: Token, right: Box<Expression>), visit_unary)
+    (Unary(operator: Token, right: Box<Expression>), visit_unary),
+    (Ternary(condition: Box<Expression>, then_expr: Box<Expression>, else_expr: Box<Expression>), visit_ternary),
+    (Array(elements: Vec<Expression>), visit_array),
+    (Index(object: Box<Expression>, bracket: Token, index: Box<Expression>), visit_index),
+    (IndexSet(object: Box<Expression>, bracket: Token, index: Box<Expression>, operator: Option<Token>, value: Box<Expression>), visit_index_set),
+    (Interpolation(parts: Vec<Expression>), visit_interpolation),
+    (Logical(left: Box<Expression>, operator: Token, right: Box<Expression>), visit_logical),
+    (Map(brace: Token, entries: Vec<(Expression, Expression)>), visit_map)
+}
+
+// 用于编写 AST pass：只需要描述怎么变换单个节点，递归由这里负责
+impl Expression {
+    // 对每个直接子节点应用 f，重新组装出当前节点
+    pub fn map_children<F: FnMut(Expression) -> Expression>(self, mut f: F) -> Expression {
+        let mut map = |expr: Box<Expression>| Box::new(f(*expr));
+
+        match self {
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => Expression::Binary {
+                left: map(left),
+                operator,
+                right: map(right),
+            },
+            Expression::Grouping { expr } => Expression::Grouping { expr: map(expr) },
+            Expression::Unary { operator, right } => Expression::Unary {
+                operator,
+                right: map(right),
+            },
+            Expression::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => Expression::Ternary {
+                condition: map(condition),
+                then_expr: map(then_expr),
+                else_expr: map(else_expr),
+            },
+            Expression::Array { elements } => Expression::Array {
+                elements: elements.into_iter().map(|e| *map(Box::new(e))).collect(),
+            },
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => Expression::Index {
+                object: map(object),
+                bracket,
+                index: map(index),
+            },
+            Expression::IndexSet {
+                object,
+                bracket,
+                index,
+                operator,
+                value,
+            } => Expression::IndexSet {
+                object: map(object),
+                bracket,
+                index: map(index),
+                operator,
+                value: map(value),
+            },
+            Expression::Interpolation { parts } => Expression::Interpolation {
+                parts: parts.into_iter().map(|e| *map(Box::new(e))).collect(),
+            },
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => Expression::Logical {
+                left: map(left),
+                operator,
+                right: map(right),
+            },
+            Expression::Map { brace, entries } => Expression::Map {
+                brace,
+                entries: entries
+                    .into_iter()
+                    .map(|(key, value)| (*map(Box::new(key)), *map(Box::new(value))))
+                    .collect(),
+            },
+            literal @ Expression::Literal { .. } => literal,
+        }
+    }
+
+    // 自底向上地变换整棵树：先变换子节点，再对变换后的当前节点应用 f
+    pub fn walk<F: FnMut(Expression) -> Expression>(self, f: &mut F) -> Expression {
+        grow_stack(|| {
+            let expr = self.map_children(|child| child.walk(f));
+            f(expr)
+        })
+    }
+}
+
+// 直接输出 S 表达式形式，等价于 expr.accept(&AstPrinter)
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.accept(&AstPrinter))
+    }
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExprLiteral {
     String(String), // strings
+    Int(i64),       // integers
     Number(f64),    // numbers
     Nil,            // nil
     Bool(bool),     // true or false
@@ -70,6 +198,9 @@ impl Debug for ExprLiteral {
             ExprLiteral::String(s) => {
                 write!(f, "string:\"{}\"", s)
             }
+            ExprLiteral::Int(i) => {
+                write!(f, "int:\"{}\"", i)
+            }
             ExprLiteral::Number(fl) => {
                 write!(f, "number:\"{}\"", fl)
             }
@@ -86,12 +217,29 @@ impl Debug for ExprLiteral {
     }
 }
 
+// 结构上的相等：数字按二进制位比较，这样 NaN 字面量也等于它自己
+impl PartialEq for ExprLiteral {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ExprLiteral::String(a), ExprLiteral::String(b)) => a == b,
+            (ExprLiteral::Int(a), ExprLiteral::Int(b)) => a == b,
+            (ExprLiteral::Number(a), ExprLiteral::Number(b)) => a.to_bits() == b.to_bits(),
+            (ExprLiteral::Nil, ExprLiteral::Nil) => true,
+            (ExprLiteral::Bool(a), ExprLiteral::Bool(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 impl Display for ExprLiteral {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ExprLiteral::String(s) => {
                 write!(f, "{}", s)
             }
+            ExprLiteral::Int(i) => {
+                write!(f, "{}", i)
+            }
             ExprLiteral::Number(fl) => {
                 write!(f, "{}", fl)
             }
@@ -108,15 +256,26 @@ impl Display for ExprLiteral {
     }
 }
 
-#[cfg(test)]
+// 把 AST 序列化成 JSON，便于缓存到磁盘或者在工具之间传递
+#[cfg(feature = "serde")]
+pub fn ast_to_json(expr: &Expression) -> serde_json::Result<String> {
+    serde_json::to_string(expr)
+}
+
+#[cfg(feature = "serde")]
+pub fn ast_from_json(json: &str) -> serde_json::Result<Expression> {
+    serde_json::from_str(json)
+}
+
 pub mod ast_printer;
 pub mod interpreter;
+pub mod rpn_printer;
+pub mod source_printer;
 
 // 测试代码
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::expression::ast_printer::AstPrinter;
     use crate::token::{Literal, TokenType};
 
     #[test]
@@ -125,7 +284,7 @@ mod tests {
             left: Box::new(Expression::Literal {
                 value: ExprLiteral::String("1".to_string()),
             }),
-            operator: Token::new(TokenType::Plus, "+", Literal::None, 1),
+            operator: Token::new(TokenType::Plus, "+", Literal::None, 1, 0),
             right: Box::new(Expression::Grouping {
                 expr: Box::new(Expression::Literal {
                     value: ExprLiteral::String("2".to_string()),
@@ -135,4 +294,89 @@ mod tests {
 
         assert_eq!(expr.accept(&AstPrinter), "(+ 1 (group 2))");
     }
+
+    #[test]
+    fn test_display() {
+        use crate::parser::Parser;
+        use crate::scanner::Scanner;
+
+        let expr = Parser::parse(Scanner::parse("-1 + [2][0] * (3 or nil);")).remove(0);
+        assert_eq!(format!("{expr}"), expr.accept(&AstPrinter));
+        assert_eq!(
+            expr.to_string(),
+            "(+ (- 1) (* (index (list 2) 0) (group (or 3 nil))))"
+        );
+    }
+
+    #[test]
+    fn test_bool_literal() {
+        use crate::parser::Parser;
+        use crate::scanner::Scanner;
+
+        let program = Parser::parse(Scanner::parse("true; false;"));
+        assert_eq!(
+            program,
+            [
+                Expression::Literal {
+                    value: ExprLiteral::Bool(true)
+                },
+                Expression::Literal {
+                    value: ExprLiteral::Bool(false)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        use crate::parser::Parser;
+        use crate::scanner::Scanner;
+
+        let source = "[1, \"a${2 + 3}\"][0] ? -4 ** 0.5 : nil;";
+        let first = Parser::parse(Scanner::parse(source));
+        let second = Parser::parse(Scanner::parse(source));
+        assert_eq!(first, second);
+        assert_ne!(first, Parser::parse(Scanner::parse("1 + 2;")));
+
+        let nan = Expression::Literal {
+            value: ExprLiteral::Number(f64::NAN),
+        };
+        assert_eq!(nan, nan.clone());
+    }
+
+    #[test]
+    fn test_identity_walk() {
+        use crate::parser::Parser;
+        use crate::scanner::Scanner;
+
+        let source = "-(1 + 2) ? [3, \"a${4}b\"][0] : ([[5]][0][0] = 6 ** 2);";
+        let expr = Parser::parse(Scanner::parse(source)).remove(0);
+        let expected = expr.accept(&AstPrinter);
+
+        let mut visited = 0;
+        let walked = expr.clone().walk(&mut |e| {
+            visited += 1;
+            e
+        });
+        assert_eq!(walked, expr);
+        assert_eq!(walked.accept(&AstPrinter), expected);
+        assert_eq!(visited, 25);
+
+        let mapped = expr.map_children(|e| e);
+        assert_eq!(mapped.accept(&AstPrinter), expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ast_json_round_trip() {
+        use crate::parser::Parser;
+        use crate::scanner::Scanner;
+
+        let expr = Parser::parse(Scanner::parse("1 + (2 * 3)")).remove(0);
+        let json = ast_to_json(&expr).unwrap();
+        let decoded = ast_from_json(&json).unwrap();
+
+        assert_eq!(decoded.accept(&AstPrinter), expr.accept(&AstPrinter));
+        assert_eq!(decoded.accept(&AstPrinter), "(+ 1 (group (* 2 3)))");
+    }
 }
@@ -0,0 +1,110 @@
+use crate::expression::{ExprLiteral, ExprVisitor, Expression};
+use crate::token::{Token, TokenType};
+
+// 以逆波兰表达式输出 AST：操作数在前，运算符在后，例如 1 + 2 * 3 → 1 2 3 * +
+// 分组在逆波兰表达式中不需要括号，直接省略；一元负号写作 neg，以便和减号区分；
+// 元素个数可变的节点在后面带上个数，例如 [1, 2] → 1 2 list/2
+pub struct RpnPrinter;
+
+impl RpnPrinter {
+    fn postfix<'a>(&self, operands: impl IntoIterator<Item = &'a Expression>, op: &str) -> String {
+        let mut out: Vec<_> = operands.into_iter().map(|e| e.accept(self)).collect();
+        out.push(op.to_string());
+        out.join(" ")
+    }
+}
+
+impl ExprVisitor<String> for RpnPrinter {
+    fn visit_binary(&self, left: &Box<Expression>, op: &Token, right: &Box<Expression>) -> String {
+        self.postfix([&**left, &**right], op.lexeme())
+    }
+
+    fn visit_literal(&self, value: &ExprLiteral) -> String {
+        value.to_string()
+    }
+
+    fn visit_grouping(&self, expr: &Box<Expression>) -> String {
+        expr.accept(self)
+    }
+
+    fn visit_unary(&self, operator: &Token, right: &Box<Expression>) -> String {
+        let op = match operator.token_type() {
+            TokenType::Minus => "neg",
+            _ => operator.lexeme(),
+        };
+        self.postfix([&**right], op)
+    }
+
+    fn visit_ternary(
+        &self,
+        condition: &Box<Expression>,
+        then_expr: &Box<Expression>,
+        else_expr: &Box<Expression>,
+    ) -> String {
+        self.postfix([&**condition, &**then_expr, &**else_expr], "?:")
+    }
+
+    fn visit_array(&self, elements: &Vec<Expression>) -> String {
+        self.postfix(elements, &format!("list/{}", elements.len()))
+    }
+
+    fn visit_index(
+        &self,
+        object: &Box<Expression>,
+        _bracket: &Token,
+        index: &Box<Expression>,
+    ) -> String {
+        self.postfix([&**object, &**index], "index")
+    }
+
+    fn visit_index_set(
+        &self,
+        object: &Box<Expression>,
+        _bracket: &Token,
+        index: &Box<Expression>,
+        operator: &Option<Token>,
+        value: &Box<Expression>,
+    ) -> String {
+        let name = match operator {
+            Some(operator) => format!("set-index{}=", operator.lexeme()),
+            None => "set-index".to_string(),
+        };
+        self.postfix([&**object, &**index, &**value], &name)
+    }
+
+    fn visit_interpolation(&self, parts: &Vec<Expression>) -> String {
+        self.postfix(parts, &format!("interpolate/{}", parts.len()))
+    }
+
+    fn visit_logical(&self, left: &Box<Expression>, op: &Token, right: &Box<Expression>) -> String {
+        self.postfix([&**left, &**right], op.lexeme())
+    }
+
+    fn visit_map(&self, _brace: &Token, entries: &Vec<(Expression, Expression)>) -> String {
+        let operands = entries.iter().flat_map(|(key, value)| [key, value]);
+        self.postfix(operands, &format!("map/{}", entries.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expression::rpn_printer::RpnPrinter;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn rpn(source_code: &str) -> String {
+        let program = Parser::parse(Scanner::parse(source_code));
+        program[0].accept(&RpnPrinter)
+    }
+
+    #[test]
+    fn test_rpn() {
+        assert_eq!(rpn("1 + 2 * 3;"), "1 2 3 * +");
+        assert_eq!(rpn("(1 + 2) * 3;"), "1 2 + 3 *");
+        assert_eq!(rpn("-(4 - 1) ** 2;"), "4 1 - neg 2 **");
+        assert_eq!(rpn("!true == false;"), "true ! false ==");
+        assert_eq!(rpn("1 < 2 ? \"a\" : nil or 3;"), "1 2 < a nil 3 or ?:");
+        assert_eq!(rpn("[1, 2 + 3][0];"), "1 2 3 + list/2 0 index");
+        assert_eq!(rpn("[];"), "list/0");
+    }
+}
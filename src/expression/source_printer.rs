@@ -0,0 +1,260 @@
+use crate::expression::{ExprLiteral, ExprVisitor, Expression};
+use crate::token::{Token, TokenType};
+
+// 把 AST 重新输出为 Lox 源码，只在优先级需要的时候才加括号
+pub struct SourcePrinter;
+
+// 优先级从低到高，与 parser 中的语法规则一一对应
+const ASSIGNMENT: u8 = 1;
+const TERNARY: u8 = 2;
+const LOGIC_OR: u8 = 3;
+const LOGIC_AND: u8 = 4;
+const EQUALITY: u8 = 5;
+const COMPARISON: u8 = 6;
+const BIT_OR: u8 = 7;
+const BIT_XOR: u8 = 8;
+const BIT_AND: u8 = 9;
+const SHIFT: u8 = 10;
+const TERM: u8 = 11;
+const FACTOR: u8 = 12;
+const POWER: u8 = 13;
+const UNARY: u8 = 14;
+const CALL: u8 = 15;
+const PRIMARY: u8 = 16;
+
+fn binary_precedence(operator: &Token) -> u8 {
+    use TokenType::*;
+
+    match operator.token_type() {
+        Or | QuestionQuestion => LOGIC_OR,
+        And => LOGIC_AND,
+        BangEqual | EqualEqual => EQUALITY,
+        Greater | GreaterEqual | Less | LessEqual => COMPARISON,
+        Pipe => BIT_OR,
+        Caret => BIT_XOR,
+        Ampersand => BIT_AND,
+        LessLess | GreaterGreater => SHIFT,
+        Minus | Plus => TERM,
+        Slash | Star => FACTOR,
+        StarStar => POWER,
+        _ => PRIMARY,
+    }
+}
+
+fn precedence(expr: &Expression) -> u8 {
+    match expr {
+        Expression::IndexSet { .. } => ASSIGNMENT,
+        Expression::Ternary { .. } => TERNARY,
+        Expression::Binary { operator, .. } | Expression::Logical { operator, .. } => {
+            binary_precedence(operator)
+        }
+        Expression::Unary { .. } => UNARY,
+        // 常量折叠可能产生负数字面量，输出时带有负号
+        Expression::Literal {
+            value: ExprLiteral::Number(n),
+        } if n.is_sign_negative() => UNARY,
+        Expression::Literal {
+            value: ExprLiteral::Int(i),
+        } if *i < 0 => UNARY,
+        Expression::Index { .. } => CALL,
+        Expression::Literal { .. }
+        | Expression::Grouping { .. }
+        | Expression::Array { .. }
+        | Expression::Map { .. }
+        | Expression::Interpolation { .. } => PRIMARY,
+    }
+}
+
+// 普通字符串中的 "${" 需要转义，否则会被当作插值
+fn escape_string(s: &str) -> String {
+    s.replace("${", "\\${")
+}
+
+impl SourcePrinter {
+    // 子表达式的优先级低于 min 时加上括号
+    fn operand(&self, expr: &Expression, min: u8) -> String {
+        let source = expr.accept(self);
+        if precedence(expr) < min {
+            format!("({source})")
+        } else {
+            source
+        }
+    }
+}
+
+impl ExprVisitor<String> for SourcePrinter {
+    fn visit_binary(&self, left: &Box<Expression>, op: &Token, right: &Box<Expression>) -> String {
+        let prec = binary_precedence(op);
+        // 只有 ** 是右结合的
+        let (left_min, right_min) = if op.token_type() == TokenType::StarStar {
+            (prec + 1, prec)
+        } else {
+            (prec, prec + 1)
+        };
+
+        format!(
+            "{} {} {}",
+            self.operand(left, left_min),
+            op.lexeme(),
+            self.operand(right, right_min)
+        )
+    }
+
+    fn visit_logical(&self, left: &Box<Expression>, op: &Token, right: &Box<Expression>) -> String {
+        self.visit_binary(left, op, right)
+    }
+
+    fn visit_literal(&self, value: &ExprLiteral) -> String {
+        match value {
+            ExprLiteral::String(s) => format!("\"{}\"", escape_string(s)),
+            // 保留小数点，否则重新解析时会变成整数
+            ExprLiteral::Number(n) if n.is_finite() && n.fract() == 0_f64 => format!("{n}.0"),
+            value => value.to_string(),
+        }
+    }
+
+    fn visit_grouping(&self, expr: &Box<Expression>) -> String {
+        format!("({})", expr.accept(self))
+    }
+
+    fn visit_unary(&self, operator: &Token, right: &Box<Expression>) -> String {
+        format!("{}{}", operator.lexeme(), self.operand(right, UNARY))
+    }
+
+    fn visit_ternary(
+        &self,
+        condition: &Box<Expression>,
+        then_expr: &Box<Expression>,
+        else_expr: &Box<Expression>,
+    ) -> String {
+        format!(
+            "{} ? {} : {}",
+            self.operand(condition, LOGIC_OR),
+            then_expr.accept(self),
+            self.operand(else_expr, TERNARY)
+        )
+    }
+
+    fn visit_array(&self, elements: &Vec<Expression>) -> String {
+        let elements: Vec<_> = elements.iter().map(|e| e.accept(self)).collect();
+        format!("[{}]", elements.join(", "))
+    }
+
+    fn visit_index(
+        &self,
+        object: &Box<Expression>,
+        _bracket: &Token,
+        index: &Box<Expression>,
+    ) -> String {
+        format!("{}[{}]", self.operand(object, CALL), index.accept(self))
+    }
+
+    fn visit_index_set(
+        &self,
+        object: &Box<Expression>,
+        _bracket: &Token,
+        index: &Box<Expression>,
+        operator: &Option<Token>,
+        value: &Box<Expression>,
+    ) -> String {
+        format!(
+            "{}[{}] {}= {}",
+            self.operand(object, CALL),
+            index.accept(self),
+            operator.as_ref().map_or("", |operator| operator.lexeme()),
+            value.accept(self)
+        )
+    }
+
+    fn visit_map(&self, _brace: &Token, entries: &Vec<(Expression, Expression)>) -> String {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key.accept(self), value.accept(self)))
+            .collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+
+    // 字符串片段直接写回，其他部分放进 "${...}" 中
+    fn visit_interpolation(&self, parts: &Vec<Expression>) -> String {
+        let mut out = "\"".to_string();
+        for part in parts {
+            match part {
+                Expression::Literal {
+                    value: ExprLiteral::String(s),
+                } => out.push_str(&escape_string(s)),
+                expr => out.push_str(&format!("${{{}}}", expr.accept(self))),
+            }
+        }
+        out.push('"');
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expression::ast_printer::AstPrinter;
+    use crate::expression::source_printer::SourcePrinter;
+    use crate::expression::{ExprLiteral, Expression};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::token::{Literal, Token, TokenType};
+
+    fn parse(source_code: &str) -> Expression {
+        Parser::parse(Scanner::parse(source_code)).remove(0)
+    }
+
+    fn assert_round_trip(source_code: &str, expected: &str) {
+        let expr = parse(source_code);
+        let printed = expr.accept(&SourcePrinter);
+        assert_eq!(printed, expected);
+
+        let reparsed = parse(&format!("{printed};"));
+        assert_eq!(reparsed.accept(&AstPrinter), expr.accept(&AstPrinter));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_round_trip("1+2*3;", "1 + 2 * 3");
+        assert_round_trip("(1+2)*3;", "(1 + 2) * 3");
+        assert_round_trip("1-(2-3);", "1 - (2 - 3)");
+        assert_round_trip("2**3**2;", "2 ** 3 ** 2");
+        assert_round_trip("-!true;", "-!true");
+        assert_round_trip(
+            "(true?1:false)?2:nil?3:4;",
+            "(true ? 1 : false) ? 2 : nil ? 3 : 4",
+        );
+        assert_round_trip("[1,[2,],][0][1]=\"x\";", "[1, [2]][0][1] = \"x\"");
+        assert_round_trip("[1][0]+=2*3;", "[1][0] += 2 * 3");
+        assert_round_trip("\"a${1 + 2}b\\${c}\";", "\"a${1 + 2}b\\${c}\"");
+        assert_round_trip("1 | 2 ^ 3 & 4 << 5;", "1 | 2 ^ 3 & 4 << 5");
+        assert_round_trip("(1 or 2) and 3 or nil;", "(1 or 2) and 3 or nil");
+        assert_round_trip("nil ?? (1 ?? 2) or 3;", "nil ?? (1 ?? 2) or 3");
+        assert_round_trip("1.0 + 2.5 / 3;", "1.0 + 2.5 / 3");
+        assert_round_trip("{\"a\":1,2:[3],}[\"a\"];", "{\"a\": 1, 2: [3]}[\"a\"]");
+        assert_round_trip("{};", "{}");
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        let number = |i: i64| {
+            Box::new(Expression::Literal {
+                value: ExprLiteral::Int(i),
+            })
+        };
+        let token = |token_type: TokenType, lexeme: &str| {
+            Token::new(token_type, lexeme, Literal::None, 1, 0)
+        };
+        // 手动构造、没有 Grouping 节点的 (1 + 2) * 3
+        let expr = Expression::Binary {
+            left: Box::new(Expression::Binary {
+                left: number(1),
+                operator: token(TokenType::Plus, "+"),
+                right: number(2),
+            }),
+            operator: token(TokenType::Star, "*"),
+            right: number(3),
+        };
+
+        assert_eq!(expr.accept(&SourcePrinter), "(1 + 2) * 3");
+    }
+}
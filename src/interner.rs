@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// 驻留之后的字符串，拷贝只是增加引用计数。
+// 同一个 Interner 产生的相同字符串共享同一块内存，比较时先比较地址，地址不同时再比较内容，
+// 所以不经过 Interner 创建的 Symbol 也能正确比较
+#[derive(Clone, Eq)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // 两个 Symbol 是否共享同一块内存，也就是是否来自同一次驻留
+    pub fn ptr_eq(&self, other: &Symbol) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || self.0 == other.0
+    }
+}
+
+// 按内容计算哈希，与 PartialEq 保持一致
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Symbol(Rc::from(value))
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(value: String) -> Self {
+        Symbol(Rc::from(value))
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// 序列化时直接输出字符串内容，反序列化时不再驻留
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        Ok(Symbol::from(s.as_ref()))
+    }
+}
+
+// 字符串驻留表，由 Scanner 持有。Scanner 和它产生的 token 都被丢弃之后，驻留的字符串也随之释放
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Interner {
+    strings: HashSet<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(string) = self.strings.get(s) {
+            return Symbol(string.clone());
+        }
+
+        let string: Rc<str> = Rc::from(s);
+        self.strings.insert(string.clone());
+        Symbol(string)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+    use crate::token::{Literal, TokenType};
+
+    #[test]
+    fn test_intern_once() {
+        let mut interner = Interner::new();
+        let first = interner.intern("counter");
+        for _ in 0..1000 {
+            assert!(interner.intern("counter").ptr_eq(&first));
+        }
+        assert_eq!(interner.len(), 1);
+        assert_eq!(first.as_str(), "counter");
+
+        // 没有驻留的 Symbol 按内容比较
+        assert_eq!(Symbol::from("counter"), first);
+        assert!(!Symbol::from("counter").ptr_eq(&first));
+    }
+
+    #[test]
+    fn test_scanner_shares_symbols() {
+        let tokens = Scanner::parse("counter \"text\" ".repeat(1000));
+        let identifiers: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.token_type() == TokenType::Identifier)
+            .collect();
+
+        assert_eq!(identifiers.len(), 1000);
+        assert!(identifiers
+            .iter()
+            .all(|t| t.symbol().ptr_eq(identifiers[0].symbol())));
+        assert_eq!(identifiers[0].lexeme(), "counter");
+
+        // 字符串字面量的值同样被驻留，不会每个 token 都复制一份
+        let values: Vec<_> = tokens
+            .iter()
+            .filter_map(|t| match t.literal() {
+                Literal::String(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values.len(), 1000);
+        assert!(values.iter().all(|s| s.ptr_eq(values[0])));
+        assert_eq!(values[0].as_str(), "text");
+    }
+}
@@ -0,0 +1,136 @@
+use crate::error::LoxError;
+use crate::source_map::SourceMap;
+use crate::token::{Literal, Token};
+use std::fmt::Write;
+
+// 把字符串转换成 JSON 字符串字面量（包含两边的引号）
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn literal_to_json(literal: &Literal) -> String {
+    match literal {
+        Literal::String(s) => escape(s.as_str()),
+        Literal::Int(i) => i.to_string(),
+        Literal::Number(n) => n.to_string(),
+        Literal::Bool(b) => b.to_string(),
+        Literal::None => "null".to_string(),
+    }
+}
+
+// 每个 token 输出为 {"type", "lexeme", "literal", "line", "column"}
+pub fn tokens_to_json(tokens: &[Token], source_map: &SourceMap) -> String {
+    let mut out = String::from("[");
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let (_, column) = source_map.offset_to_line_col(token.start());
+        write!(
+            out,
+            "{{\"type\":{},\"lexeme\":{},\"literal\":{},\"line\":{},\"column\":{}}}",
+            escape(&format!("{:?}", token.token_type())),
+            escape(token.lexeme()),
+            literal_to_json(token.literal()),
+            token.line(),
+            column
+        )
+        .unwrap();
+    }
+    out.push(']');
+    out
+}
+
+fn error_to_json(out: &mut String, phase: &str, line: usize, column: usize, msg: &str) {
+    if out.len() > 1 {
+        out.push(',');
+    }
+    write!(
+        out,
+        "{{\"phase\":{},\"line\":{},\"column\":{},\"message\":{}}}",
+        escape(phase),
+        line,
+        column,
+        escape(msg)
+    )
+    .unwrap();
+}
+
+// 每个错误输出为 {"phase", "line", "column", "message"}
+pub fn errors_to_json(errors: &[LoxError], source_map: &SourceMap) -> String {
+    let mut out = String::from("[");
+    let column = |offset: usize| source_map.offset_to_line_col(offset).1;
+
+    for error in errors {
+        match error {
+            LoxError::Scan(errors) => {
+                for e in errors {
+                    error_to_json(&mut out, "scan", e.line, column(e.offset), &e.msg);
+                }
+            }
+            LoxError::Parse(errors) => {
+                for e in errors {
+                    let column = column(e.token.start());
+                    error_to_json(&mut out, "parse", e.line(), column, &e.message);
+                }
+            }
+            LoxError::Runtime(e) => {
+                let column = column(e.token.start());
+                error_to_json(&mut out, "runtime", e.line(), column, e.msg);
+            }
+        }
+    }
+    out.push(']');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn scan_to_json(source: &str) -> serde_json::Value {
+        let json = tokens_to_json(&Scanner::parse(source), &SourceMap::new(source));
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_tokens_to_json() {
+        let json = scan_to_json("1 +\n  \"a\\b\";");
+        let tokens = json.as_array().unwrap();
+
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[0]["type"], "Number");
+        assert_eq!(tokens[0]["literal"], 1.0);
+        assert_eq!(tokens[1]["lexeme"], "+");
+        assert_eq!(tokens[1]["literal"], serde_json::Value::Null);
+        assert_eq!(tokens[2]["type"], "String");
+        assert_eq!(tokens[2]["lexeme"], "\"a\\b\"");
+        assert_eq!(tokens[2]["literal"], "a\\b");
+        assert_eq!(tokens[2]["line"], 2);
+        assert_eq!(tokens[2]["column"], 3);
+    }
+
+    #[test]
+    fn test_empty_program() {
+        let json = scan_to_json("");
+        let tokens = json.as_array().unwrap();
+
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0]["type"], "EOF");
+    }
+}
@@ -0,0 +1,13 @@
+pub mod error;
+pub mod expression;
+pub mod interner;
+pub mod json;
+pub mod optimizer;
+pub mod parser;
+pub mod reporter;
+pub mod scanner;
+pub mod source_map;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+pub mod token;
+pub mod vm;
@@ -1,62 +1,553 @@
-mod expression;
-mod parser;
-mod reporter;
-mod scanner;
-mod token;
-
-use crate::expression::interpreter::Interpreter;
-use crate::parser::Parser;
-use crate::scanner::Scanner;
-use std::io::{Read, Write};
-use std::path::Path;
-use std::{env, fs, io};
+use rlox::error::LoxError;
+use rlox::expression::interpreter::{Interpreter, RuntimeError};
+use rlox::expression::Expression;
+use rlox::optimizer::fold_constants;
+use rlox::parser::Parser;
+use rlox::scanner::Scanner;
+use rlox::source_map::SourceMap;
+use rlox::vm::VM;
+use rlox::{json, reporter};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, fs, io, process};
+
+// 各个阶段的耗时，用于 --time
+#[derive(Debug, Clone, Copy)]
+struct PhaseTimings {
+    scan: Duration,
+    parse: Duration,
+    interpret: Duration,
+}
+
+impl Display for PhaseTimings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000_f64;
+        write!(
+            f,
+            "scan: {:.3}ms, parse: {:.3}ms, interpret: {:.3}ms",
+            ms(self.scan),
+            ms(self.parse),
+            ms(self.interpret)
+        )
+    }
+}
+
+// 命令行选择的运行方式
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    Prompt,
+    File(String),
+    TokensJson(String),
+    Time(String),
+    Eval(String),
+    Vm(String),
+    Check(String),
+    JsonErrors(String),
+    Strict(String),
+}
+
+// args[0] 是程序名，参数不合法时返回 None
+fn parse_args(args: &[String]) -> Option<Mode> {
+    let args: Vec<&str> = args.iter().skip(1).map(|s| s.as_str()).collect();
+
+    match args.as_slice() {
+        [] => Some(Mode::Prompt),
+        ["--tokens-json", path] => Some(Mode::TokensJson(path.to_string())),
+        ["--time", path] => Some(Mode::Time(path.to_string())),
+        ["--vm", path] => Some(Mode::Vm(path.to_string())),
+        ["--check", path] => Some(Mode::Check(path.to_string())),
+        ["--json-errors", path] => Some(Mode::JsonErrors(path.to_string())),
+        ["--strict", path] => Some(Mode::Strict(path.to_string())),
+        ["-e" | "--eval", source] => Some(Mode::Eval(source.to_string())),
+        [path] if !path.starts_with('-') => Some(Mode::File(path.to_string())),
+        _ => None,
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    match args.len() {
-        2 => run_file(&args[1]),
-        1 => run_prompt(),
-        _ => {
-            eprintln!("Usage: {} [file_path]", args[0]);
-            std::process::exit(1);
+    match parse_args(&args) {
+        Some(Mode::Prompt) => run_prompt(),
+        Some(Mode::File(path)) => {
+            or_exit(run(read_file(path)));
+        }
+        Some(Mode::TokensJson(path)) => print_tokens_json(path),
+        Some(Mode::Time(path)) => eprintln!("{}", or_exit(run(read_file(path)))),
+        Some(Mode::Eval(source)) => {
+            or_exit(run(source));
+        }
+        Some(Mode::Vm(path)) => {
+            or_exit(execute(read_file(path), VM::interpret));
+        }
+        Some(Mode::Check(path)) => {
+            let errors = check(read_file(path));
+            for err in &errors {
+                reporter::report(err);
+            }
+            if let Some(err) = errors.first() {
+                process::exit(exit_code_for(err));
+            }
+        }
+        Some(Mode::Strict(path)) => {
+            or_exit(execute(read_file(path), Interpreter::interpret_strict));
+        }
+        Some(Mode::JsonErrors(path)) => {
+            // 诊断信息以 JSON 数组的形式输出到 stdout，放在程序本身的输出之后
+            let source = read_file(path);
+            let map = SourceMap::new(&source);
+            let errors = collect_errors(source, Some(Interpreter::interpret));
+            println!("{}", json::errors_to_json(&errors, &map));
+            if let Some(err) = errors.first() {
+                process::exit(exit_code_for(err));
+            }
+        }
+        None => {
+            eprintln!(
+                "Usage: {} [--tokens-json | --time | --vm | --check | --json-errors | --strict] [file_path] | -e <code>",
+                args[0]
+            );
+            process::exit(1);
+        }
+    }
+}
+
+// REPL 最多保留多少条历史记录
+const HISTORY_CAPACITY: usize = 1000;
+
+// REPL 的历史记录，每一条是一行输入。忽略空行和与上一条相同的输入，超过容量时丢弃最早的记录
+struct History {
+    entries: VecDeque<String>,
+    capacity: usize,
+}
+
+impl History {
+    fn new(capacity: usize) -> Self {
+        History {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        let line = line.trim_end();
+        if line.trim().is_empty() || self.entries.back().is_some_and(|last| last == line) {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(line.to_string());
+    }
+
+    // 从旧到新遍历所有记录
+    fn iter(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    // 历史记录文件每行一条，文件不存在时相当于没有历史记录
+    fn load(&mut self, path: &Path) {
+        if let Ok(content) = fs::read_to_string(path) {
+            content.lines().for_each(|line| self.push(line));
         }
     }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut content = String::new();
+        for line in self.iter() {
+            content.push_str(line);
+            content.push('\n');
+        }
+        fs::write(path, content)
+    }
+}
+
+// 交互式使用时，历史记录保存在 ~/.rlox_history 中；通过管道输入时不保存
+fn history_file() -> Option<PathBuf> {
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    env::var_os("HOME").map(|home| Path::new(&home).join(".rlox_history"))
 }
 
 fn run_prompt() {
-    let stdin = io::stdin();
-    let mut input = String::new();
+    let path = history_file();
+    let mut history = History::new(HISTORY_CAPACITY);
+    if let Some(path) = &path {
+        history.load(path);
+    }
 
-    loop {
-        print!("> ");
+    // 交互模式下报告错误之后继续读入下一行
+    let report_errors = |source| {
+        if let Err(err) = run(source) {
+            reporter::report(&err);
+        }
+    };
+
+    // 开启 readline feature 并且能打开终端时使用行编辑器，否则退回到逐行读取 stdin
+    #[cfg(feature = "readline")]
+    let editor = rustyline::DefaultEditor::new().ok();
+    #[cfg(feature = "readline")]
+    if let Some(mut editor) = editor {
+        for line in history.iter() {
+            let _ = editor.add_history_entry(line);
+        }
+        let read_line = |prompt: &str| {
+            let line = editor.readline(prompt).ok()?;
+            let _ = editor.add_history_entry(line.as_str());
+            Some(line + "\n")
+        };
+        repl(read_line, &mut history, report_errors);
+    } else {
+        repl(read_lines(io::stdin().lock()), &mut history, report_errors);
+    }
+
+    #[cfg(not(feature = "readline"))]
+    repl(read_lines(io::stdin().lock()), &mut history, report_errors);
+
+    if let Some(path) = &path {
+        // 历史记录保存失败不影响退出
+        let _ = history.save(path);
+    }
+}
+
+// 没有行编辑器时的输入方式：打印提示符，然后从 reader 中读入一行（包含换行符）
+fn read_lines(mut reader: impl BufRead) -> impl FnMut(&str) -> Option<String> {
+    move |prompt| {
+        print!("{prompt}");
         io::stdout().flush().expect("fail to flush");
 
-        stdin
-            .read_line(&mut input)
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
             .expect("fail to read from terminal");
+        (read > 0).then_some(line)
+    }
+}
+
+// 括号、字符串没有闭合时继续读入下一行，直到输入完整之后再交给 run。
+// read_line 返回带换行符的一行输入，没有更多输入时返回 None；读入的每一行都会记入 history
+fn repl(
+    mut read_line: impl FnMut(&str) -> Option<String>,
+    history: &mut History,
+    mut run: impl FnMut(String),
+) {
+    let mut input = String::new();
 
-        if input == "\n" {
+    loop {
+        let prompt = if input.is_empty() { "> " } else { "... " };
+        let Some(line) = read_line(prompt) else {
             break;
+        };
+        history.push(&line);
+        input.push_str(&line);
+
+        // 行尾可能是 "\n" 或者 "\r\n"
+        let content = input.trim_end_matches(['\r', '\n']);
+        if content.is_empty() {
+            break;
+        }
+
+        // 行尾的 \ 表示显式续行，去掉 \ 之后继续读入下一行（保留换行符，行号不变）
+        if content.ends_with('\\') {
+            input.truncate(content.len() - 1);
+            input.push('\n');
+            continue;
         }
 
-        run(input.clone());
-        input.clear();
+        if Scanner::is_complete(&input) {
+            run(input.clone());
+            input.clear();
+        }
     }
 }
 
-fn run_file(path: impl AsRef<Path>) {
+fn read_file(path: impl AsRef<Path>) -> String {
     let mut file = fs::File::open(path).expect("fail to find given file");
     let mut content = String::new();
 
     file.read_to_string(&mut content)
         .expect("fail to read given file");
 
-    run(content);
+    content
+}
+
+// 退出码与 sysexits.h 保持一致：65 表示输入有误，70 表示运行时出错，成功时为 0
+fn exit_code_for(err: &LoxError) -> i32 {
+    match err {
+        LoxError::Scan(_) | LoxError::Parse(_) => 65,
+        LoxError::Runtime(_) => 70,
+    }
+}
+
+// 出错时报告错误，并以对应的退出码退出
+fn or_exit<T>(result: Result<T, LoxError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            reporter::report(&err);
+            process::exit(exit_code_for(&err));
+        }
+    }
+}
+
+fn print_tokens_json(path: impl AsRef<Path>) {
+    let content = read_file(path);
+    let tokens = Scanner::parse(&content);
+    println!(
+        "{}",
+        json::tokens_to_json(&tokens, &SourceMap::new(&content))
+    );
+}
+
+// 只做词法和语法分析，不执行代码，用于 --check
+fn check(source_code: String) -> Vec<LoxError> {
+    reporter::set_source(&source_code);
+    collect_errors(source_code, None)
+}
+
+// 收集所有阶段的错误：有词法错误时仍然继续语法分析，一次就能看到全部的诊断信息；
+// 给出了执行后端并且没有错误时执行程序，运行时错误同样被收集起来
+fn collect_errors(source_code: String, interpret: Option<Backend>) -> Vec<LoxError> {
+    let mut scanner = Scanner::new(source_code);
+    let tokens: Vec<_> = scanner.by_ref().collect();
+    let mut errors = vec![];
+    if !scanner.errors().is_empty() {
+        errors.push(LoxError::Scan(scanner.errors().to_vec()));
+    }
+
+    match Parser::parse_program(tokens) {
+        Err(parse_errors) => errors.push(LoxError::Parse(parse_errors)),
+        Ok(program) if errors.is_empty() => {
+            let Some(interpret) = interpret else {
+                return errors;
+            };
+            let program: Vec<_> = program.into_iter().map(fold_constants).collect();
+            if let Err(err) = interpret(&program) {
+                errors.push(LoxError::Runtime(err));
+            }
+        }
+        Ok(_) => (),
+    }
+
+    errors
+}
+
+fn run(source_code: String) -> Result<PhaseTimings, LoxError> {
+    execute(source_code, Interpreter::interpret)
 }
 
-fn run(source_code: String) {
-    let tokens = Scanner::parse(source_code);
-    let expr = Parser::parse(tokens);
-    Interpreter::interpret(&expr);
+// 执行后端可以是解释器或者虚拟机
+type Backend = fn(&[Expression]) -> Result<(), RuntimeError>;
+
+fn execute(source_code: String, interpret: Backend) -> Result<PhaseTimings, LoxError> {
+    reporter::set_source(&source_code);
+
+    let start = Instant::now();
+    let tokens = Scanner::scan(source_code)?;
+    let scanned = Instant::now();
+    let program: Vec<_> = Parser::parse_program(tokens)?
+        .into_iter()
+        .map(fold_constants)
+        .collect();
+    let parsed = Instant::now();
+    interpret(&program)?;
+
+    Ok(PhaseTimings {
+        scan: scanned - start,
+        parse: parsed - scanned,
+        interpret: parsed.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let parse = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            parse_args(&args)
+        };
+
+        assert_eq!(parse(&["rlox"]), Some(Mode::Prompt));
+        assert_eq!(parse(&["rlox", "a.lox"]), Some(Mode::File("a.lox".into())));
+        assert_eq!(
+            parse(&["rlox", "-e", "1 + 2;"]),
+            Some(Mode::Eval("1 + 2;".into()))
+        );
+        assert_eq!(
+            parse(&["rlox", "--eval", "[1][0];"]),
+            Some(Mode::Eval("[1][0];".into()))
+        );
+        assert_eq!(
+            parse(&["rlox", "--vm", "a.lox"]),
+            Some(Mode::Vm("a.lox".into()))
+        );
+        assert_eq!(
+            parse(&["rlox", "--json-errors", "a.lox"]),
+            Some(Mode::JsonErrors("a.lox".into()))
+        );
+        assert_eq!(
+            parse(&["rlox", "--strict", "a.lox"]),
+            Some(Mode::Strict("a.lox".into()))
+        );
+        assert_eq!(
+            parse(&["rlox", "--check", "a.lox"]),
+            Some(Mode::Check("a.lox".into()))
+        );
+        assert_eq!(
+            parse(&["rlox", "--time", "a.lox"]),
+            Some(Mode::Time("a.lox".into()))
+        );
+        // -e 不能和文件一起使用
+        assert_eq!(parse(&["rlox", "-e", "1;", "a.lox"]), None);
+        assert_eq!(parse(&["rlox", "a.lox", "-e", "1;"]), None);
+        assert_eq!(parse(&["rlox", "-e"]), None);
+    }
+
+    #[test]
+    fn test_repl_continuation() {
+        let lines = "1 + 2;\n[1,\n  2,\n][1];\n\"a\nb\";\n\n3;\n";
+        let mut sources = vec![];
+        let mut history = History::new(HISTORY_CAPACITY);
+        repl(read_lines(lines.as_bytes()), &mut history, |source| {
+            sources.push(source)
+        });
+
+        assert_eq!(sources, ["1 + 2;\n", "[1,\n  2,\n][1];\n", "\"a\nb\";\n"]);
+        assert_eq!(history.iter().last(), Some("b\";"));
+        assert_eq!(history.iter().count(), 6);
+    }
+
+    #[test]
+    fn test_repl_backslash_continuation() {
+        let lines = "1 +\\\n  2\\\n  * 3;\n4;\n";
+        let mut sources = vec![];
+        repl(
+            read_lines(lines.as_bytes()),
+            &mut History::new(HISTORY_CAPACITY),
+            |source| sources.push(source),
+        );
+
+        assert_eq!(sources, ["1 +\n  2\n  * 3;\n", "4;\n"]);
+
+        // Windows 的换行符
+        let lines = "1 +\\\r\n  2;\r\n\r\n3;\r\n";
+        let mut sources = vec![];
+        repl(
+            read_lines(lines.as_bytes()),
+            &mut History::new(HISTORY_CAPACITY),
+            |source| sources.push(source),
+        );
+
+        assert_eq!(sources, ["1 +\n  2;\r\n"]);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new(3);
+        assert_eq!(history.iter().next(), None);
+
+        for line in ["1;\n", "  \n", "2;\n", "2;", "3;\n", "4;\n"] {
+            history.push(line);
+        }
+        // 空行和连续重复的输入被忽略，超过容量时丢弃最早的 "1;"
+        assert_eq!(history.iter().collect::<Vec<_>>(), ["2;", "3;", "4;"]);
+
+        let path = env::temp_dir().join(format!("rlox_history_{}", process::id()));
+        history.save(&path).unwrap();
+        let mut loaded = History::new(3);
+        loaded.load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), ["2;", "3;", "4;"]);
+    }
+
+    #[test]
+    fn test_phase_timings() {
+        let timings = run("1 + 2; [1, 2][0] * 3;".to_string()).unwrap();
+        assert!(timings.scan >= Duration::ZERO);
+        assert!(timings.parse >= Duration::ZERO);
+        assert!(timings.interpret >= Duration::ZERO);
+
+        let summary = timings.to_string();
+        assert!(summary.starts_with("scan: "));
+        assert!(summary.contains("ms, parse: "));
+        assert!(summary.contains("ms, interpret: "));
+    }
+
+    #[test]
+    fn test_exit_code_for() {
+        let exit_code = |source: &str| match run(source.to_string()) {
+            Ok(_) => 0,
+            Err(err) => exit_code_for(&err),
+        };
+
+        assert_eq!(exit_code("1 + 2;"), 0);
+        assert_eq!(exit_code("\"abc"), 65);
+        assert_eq!(exit_code("1 +;"), 65);
+        assert_eq!(exit_code("-\"a\";"), 70);
+        assert_eq!(exit_code("[1][2];"), 70);
+    }
+
+    #[test]
+    fn test_check() {
+        // 代码不会被执行，所以运行时错误检查不出来
+        assert!(check("1 + 2; -\"a\";".to_string()).is_empty());
+
+        let errors = check("1 +; (2; 3;".to_string());
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], LoxError::Parse(errors) if errors.len() == 2));
+        assert_eq!(exit_code_for(&errors[0]), 65);
+
+        let errors = check("1; @ #".to_string());
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], LoxError::Scan(errors) if errors.len() == 2));
+        assert_eq!(exit_code_for(&errors[0]), 65);
+
+        // 词法错误之后继续做语法分析，两种错误一起报告
+        let errors = check("1 @ +;\n(2;".to_string());
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], LoxError::Scan(errors) if errors.len() == 1));
+        assert!(matches!(&errors[1], LoxError::Parse(errors) if errors.len() == 2));
+    }
+
+    #[test]
+    fn test_json_errors() {
+        let to_json = |source: &str| {
+            let errors = collect_errors(source.to_string(), Some(Interpreter::interpret));
+            let json = json::errors_to_json(&errors, &SourceMap::new(source));
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()
+        };
+
+        assert_eq!(to_json("1 + 2;"), serde_json::json!([]));
+
+        let json = to_json("1 + 2 @;\n(3;");
+        let errors = json.as_array().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0]["phase"], "scan");
+        assert_eq!(errors[0]["line"], 1);
+        assert_eq!(errors[0]["column"], 7);
+        assert_eq!(errors[0]["message"], "Unexpected character.");
+        assert_eq!(errors[1]["phase"], "parse");
+        assert_eq!(errors[1]["line"], 2);
+        assert_eq!(errors[1]["column"], 3);
+
+        // 列号按字符计算，没有结束的字符串报告在开头的引号处
+        let json = to_json("\"é\" + 1 § 2;\n\"abc");
+        assert_eq!(json[0]["column"], 9);
+        assert_eq!(json[1]["line"], 2);
+        assert_eq!(json[1]["column"], 1);
+        assert_eq!(json[1]["message"], "Unterminated String");
+
+        let json = to_json("1;\n  -nil;");
+        assert_eq!(json[0]["phase"], "runtime");
+        assert_eq!(json[0]["line"], 2);
+        assert_eq!(json[0]["column"], 3);
+    }
 }
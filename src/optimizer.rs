@@ -0,0 +1,105 @@
+use crate::expression::interpreter::Interpreter;
+use crate::expression::{ExprLiteral, Expression};
+use crate::token::{Token, TokenType};
+
+// 常量折叠：在解释执行之前，把操作数全是字面量的 binary/unary/grouping 节点直接算成一个字面量。
+// 折叠时出现的运行时错误（类型错误、除零等）保持原样，留到运行时再报告。
+pub fn fold_constants(expr: Expression) -> Expression {
+    expr.walk(&mut fold_node)
+}
+
+// 子节点已经折叠过了，这里只需要看当前节点
+fn fold_node(expr: Expression) -> Expression {
+    match expr {
+        Expression::Grouping { expr } if is_literal(&expr) => *expr,
+        Expression::Binary {
+            ref left,
+            ref operator,
+            ref right,
+        } if is_literal(left) && is_literal(right) && !divides_by_zero(operator, right) => {
+            evaluate_or_keep(expr)
+        }
+        Expression::Unary { ref right, .. } if is_literal(right) => evaluate_or_keep(expr),
+        expr => expr,
+    }
+}
+
+#[inline]
+fn divides_by_zero(operator: &Token, right: &Expression) -> bool {
+    let zero = match right {
+        Expression::Literal {
+            value: ExprLiteral::Int(i),
+        } => *i == 0,
+        Expression::Literal {
+            value: ExprLiteral::Number(n),
+        } => *n == 0_f64,
+        _ => false,
+    };
+
+    operator.token_type() == TokenType::Slash && zero
+}
+
+#[inline]
+fn is_literal(expr: &Expression) -> bool {
+    matches!(expr, Expression::Literal { .. })
+}
+
+// 对只包含字面量的节点求值，求值失败或结果无法表示为字面量时返回原节点。
+// 使用严格模式求值：数字和字符串的拼接在严格模式下是错误，所以不会被折叠，留给运行时按实际的模式处理
+fn evaluate_or_keep(expr: Expression) -> Expression {
+    match expr
+        .accept(&Interpreter::new().with_strict(true))
+        .map(ExprLiteral::try_from)
+    {
+        Ok(Ok(value)) => Expression::Literal { value },
+        _ => expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expression::ast_printer::AstPrinter;
+    use crate::expression::Expression;
+    use crate::optimizer::fold_constants;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn fold(source_code: &str) -> Expression {
+        let tokens = Scanner::parse(source_code);
+        fold_constants(Parser::parse(tokens).remove(0))
+    }
+
+    #[test]
+    fn test_fold_constant_tree() {
+        let expr = fold("1 + 2 * 3;");
+        assert!(matches!(expr, Expression::Literal { .. }));
+        assert_eq!(expr.accept(&AstPrinter), "7");
+
+        assert_eq!(fold("!true;").accept(&AstPrinter), "false");
+        assert_eq!(fold("-(2 - 5) * 2;").accept(&AstPrinter), "6");
+        assert_eq!(fold("\"a\" + \"b\" == \"ab\";").accept(&AstPrinter), "true");
+    }
+
+    #[test]
+    fn test_fold_partially() {
+        assert_eq!(
+            fold("[1 + 2][0] * (2 * 3);").accept(&AstPrinter),
+            "(* (index (list 3) 0) 6)"
+        );
+        assert_eq!(
+            fold("(1 < 2) ? 3 * 3 : 4;").accept(&AstPrinter),
+            "(?: true 9 4)"
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors_are_deferred() {
+        assert_eq!(fold("1 / 0;").accept(&AstPrinter), "(/ 1 0)");
+        assert_eq!(fold("-\"a\";").accept(&AstPrinter), "(- a)");
+        assert_eq!(
+            fold("1 + (true - 1);").accept(&AstPrinter),
+            "(+ 1 (group (- true 1)))"
+        );
+        assert_eq!(fold("1 + \"x\";").accept(&AstPrinter), "(+ 1 x)");
+    }
+}
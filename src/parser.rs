@@ -1,51 +1,143 @@
-use crate::expression::{ExprLiteral, Expression};
-use crate::reporter::error_at_token;
+use crate::expression::source_printer::SourcePrinter;
+use crate::expression::{grow_stack, ExprLiteral, Expression, MAX_DEPTH};
+use crate::reporter;
 use crate::token::TokenType::*;
 use crate::token::{Literal, Token, TokenType};
 use std::cell::RefCell;
-use std::fmt::Display;
+use std::fmt::{Display, Formatter};
 /*
  * Lox语法规则：
- * expression     → equality ;
+ * program        → ( expression ( ";" | EOF ) )* EOF ;
+ * expression     → assignment ;
+ * assignment     → call "[" expression "]" ( "=" | "+=" | "-=" | "*=" | "/=" ) assignment
+ *                | ternary ;
+ * ternary        → logic_or ( "?" expression ":" ternary )? ;
+ * logic_or       → logic_and ( ( "or" | "??" ) logic_and )* ;
+ * logic_and      → equality ( "and" equality )* ;
  * equality       → comparison ( ( "!=" | "==" ) comparison )* ;
- * comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+ * comparison     → bit_or ( ( ">" | ">=" | "<" | "<=" ) bit_or )* ;
+ * bit_or         → bit_xor ( "|" bit_xor )* ;
+ * bit_xor        → bit_and ( "^" bit_and )* ;
+ * bit_and        → shift ( "&" shift )* ;
+ * shift          → term ( ( "<<" | ">>" ) term )* ;
  * term           → factor ( ( "-" | "+" ) factor )* ;
- * factor         → unary ( ( "/" | "*" ) unary )* ;
- * unary          → ( "!" | "-" ) unary | primary ;
- * primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" ;
+ * factor         → power ( ( "/" | "*" ) power )* ;
+ * power          → unary ( "**" power )? ;
+ * unary          → ( "!" | "-" ) unary | call ;
+ * call           → primary ( "[" expression "]" )* ;
+ * primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")"
+ *                | "[" ( expression ( "," expression )* ","? )? "]"
+ *                | "{" ( entry ( "," entry )* ","? )? "}"
+ *                | ( INTERPOLATION expression )+ STRING ;
+ * entry          → expression ":" expression ;
  */
 #[allow(unused)]
 pub struct Parser {
     tokens: Vec<Token>,
     current: RefCell<usize>,
+    depth: RefCell<usize>,
+    max_depth: usize,
 }
 
+// 行号和 lexeme 都从 token 中取，保留整个 token 是因为 reporter 和 --json-errors 还需要它的偏移量来计算列号
 #[derive(Debug)]
-pub struct ParseError;
+pub struct ParseError {
+    pub token: Token, // 出错位置的token
+    pub message: std::string::String,
+}
+
+impl ParseError {
+    pub fn line(&self) -> usize {
+        self.token.line()
+    }
+
+    pub fn lexeme(&self) -> &str {
+        self.token.lexeme()
+    }
+}
+
+// 与 reporter 的格式一致，只是没有列号和源码片段，例如：[line 1] Error at ')': message
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Error ", self.line())?;
+        if self.token.token_type() == EOF {
+            write!(f, "at end: {}", self.message)
+        } else {
+            write!(f, "at '{}': {}", self.lexeme(), self.message)
+        }
+    }
+}
 
 pub type ParseResult<T> = Result<T, ParseError>;
 
 // basic methods
 impl Parser {
     #[inline]
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        // 保证最后一个 token 是 EOF，这样 peek 永远不会越界
+        if tokens.last().map(|t| t.token_type()) != Some(EOF) {
+            let (line, start) = tokens.last().map_or((1, 0), |t| (t.line(), t.start()));
+            tokens.push(Token::new(EOF, "", Literal::None, line, start));
+        }
+
         Parser {
             tokens,
             current: RefCell::new(0),
+            depth: RefCell::new(0),
+            max_depth: MAX_DEPTH,
         }
     }
 
     #[inline]
-    fn parse_tokens(&self) -> Expression {
-        self.expression().unwrap_or(Expression::Literal {
-            value: ExprLiteral::Nil,
-        })
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
     }
 
     #[inline]
-    pub fn parse(tokens: Vec<Token>) -> Expression {
+    pub fn parse_program(tokens: Vec<Token>) -> Result<Vec<Expression>, Vec<ParseError>> {
+        Self::new(tokens).program()
+    }
+
+    // 只解析一个表达式，不需要分号，给嵌入方和 REPL 使用；表达式后面还有 EOF 以外的 token 时报错
+    pub fn parse_expression(tokens: Vec<Token>) -> Result<Expression, ParseError> {
         let parser = Self::new(tokens);
-        parser.parse_tokens()
+        let expr = parser.expression()?;
+        if !parser.is_at_end() {
+            return Err(Self::error(parser.peek(), "Expect end of expression."));
+        }
+        Ok(expr)
+    }
+
+    // 解析整个程序，出错后通过 synchronize 恢复并继续解析，最后返回所有的错误
+    pub fn program(&self) -> Result<Vec<Expression>, Vec<ParseError>> {
+        let mut program = vec![];
+        let mut errors = vec![];
+
+        while !self.is_at_end() {
+            match self.expression_statement() {
+                Ok(expr) => program.push(expr),
+                Err(err) => {
+                    errors.push(err);
+                    self.synchronize();
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // 给 main 使用的便捷方法，遇到语法错误时报告所有错误并退出
+    #[inline]
+    pub fn parse(tokens: Vec<Token>) -> Vec<Expression> {
+        match Self::parse_program(tokens) {
+            Ok(program) => program,
+            Err(errors) => reporter::parse_errors(&errors),
+        }
     }
 
     #[inline]
@@ -88,9 +180,10 @@ impl Parser {
         &self.tokens[*self.current.borrow()]
     }
 
+    // 还没有消费任何 token 时返回第一个 token，而不是越界
     #[inline]
     fn previous(&self) -> &Token {
-        &self.tokens[*self.current.borrow() - 1]
+        &self.tokens[self.current.borrow().saturating_sub(1)]
     }
 
     #[inline]
@@ -104,14 +197,155 @@ impl Parser {
 
     #[inline]
     fn error(t: &Token, msg: impl Display) -> ParseError {
-        error_at_token(t, msg);
-        ParseError
+        ParseError {
+            token: t.clone(),
+            message: msg.to_string(),
+        }
+    }
+
+    // 每进入一层递归就把深度加一，超过 max_depth 时返回错误
+    fn nested<T>(&self, f: impl FnOnce() -> ParseResult<T>) -> ParseResult<T> {
+        if *self.depth.borrow() >= self.max_depth {
+            return Err(Self::error(self.peek(), "Expression too deeply nested."));
+        }
+
+        *self.depth.borrow_mut() += 1;
+        let result = grow_stack(f);
+        *self.depth.borrow_mut() -= 1;
+        result
+    }
+
+    // 丢弃 token 直到下一条语句的开头
+    fn synchronize(&self) {
+        self.advance();
+
+        while !self.is_at_end() {
+            if self.previous().token_type() == Semicolon {
+                return;
+            }
+
+            match self.peek().token_type() {
+                Class | Fun | Var | For | If | While | Print | Return => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
     }
 }
 // methods for constructing AST
 impl Parser {
+    // 最后一个表达式后面的分号可以省略
+    fn expression_statement(&self) -> ParseResult<Expression> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            self.consume(Semicolon, "Expect ';' after expression.")?;
+        }
+
+        Ok(expr)
+    }
+
     fn expression(&self) -> ParseResult<Expression> {
-        self.equality()
+        self.assignment()
+    }
+
+    fn assignment(&self) -> ParseResult<Expression> {
+        let expr = self.ternary()?;
+
+        if self.matches(&[Equal, PlusEqual, MinusEqual, StarEqual, SlashEqual]) {
+            let equals = self.previous();
+            let value = self.nested(|| self.assignment())?;
+
+            // 目前只有列表下标可以作为赋值目标
+            return match expr {
+                Expression::Index {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    // a[i] += b 记下对应的二元运算符，求值时 a 和 i 都只会求值一次
+                    Ok(Expression::IndexSet {
+                        object,
+                        bracket,
+                        index,
+                        operator: Self::compound_operator(equals),
+                        value: Box::new(value),
+                    })
+                }
+                _ => Err(Self::error(equals, "Invalid assignment target.")),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // 复合赋值运算符对应的二元运算符，普通的 "=" 返回 None
+    fn compound_operator(equals: &Token) -> Option<Token> {
+        let (token_type, lexeme) = match equals.token_type() {
+            PlusEqual => (Plus, "+"),
+            MinusEqual => (Minus, "-"),
+            StarEqual => (Star, "*"),
+            SlashEqual => (Slash, "/"),
+            _ => return None,
+        };
+
+        Some(Token::new(
+            token_type,
+            lexeme,
+            Literal::None,
+            equals.line(),
+            equals.start(),
+        ))
+    }
+
+    fn ternary(&self) -> ParseResult<Expression> {
+        let condition = self.logic_or()?;
+
+        if self.matches(&[Question]) {
+            let then_expr = self.expression()?;
+            self.consume(Colon, "Expect ':' after then branch of ternary expression.")?;
+            let else_expr = self.nested(|| self.ternary())?;
+
+            return Ok(Expression::Ternary {
+                condition: Box::new(condition),
+                then_expr: Box::new(then_expr),
+                else_expr: Box::new(else_expr),
+            });
+        }
+
+        Ok(condition)
+    }
+
+    fn logic_or(&self) -> ParseResult<Expression> {
+        let mut expr = self.logic_and()?;
+
+        while self.matches(&[Or, QuestionQuestion]) {
+            let operator = self.previous();
+            let right = self.logic_and()?;
+            expr = Expression::Logical {
+                left: Box::new(expr),
+                operator: operator.clone(),
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn logic_and(&self) -> ParseResult<Expression> {
+        let mut expr = self.equality()?;
+
+        while self.matches(&[And]) {
+            let operator = self.previous();
+            let right = self.equality()?;
+            expr = Expression::Logical {
+                left: Box::new(expr),
+                operator: operator.clone(),
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
     }
 
     fn equality(&self) -> ParseResult<Expression> {
@@ -131,9 +365,100 @@ impl Parser {
     }
 
     fn comparison(&self) -> ParseResult<Expression> {
-        let mut expr = self.term()?;
+        let mut expr = self.bit_or()?;
 
         while self.matches(&[Greater, GreaterEqual, Less, LessEqual]) {
+            let operator = self.previous();
+            let right = self.bit_or()?;
+            // 1 < 2 < 3 会被解析成 (1 < 2) < 3，运行时再报类型错误很难看懂，这里直接给出建议
+            match &expr {
+                Expression::Binary {
+                    left: first,
+                    operator: first_operator,
+                    right: middle,
+                } if matches!(
+                    first_operator.token_type(),
+                    Greater | GreaterEqual | Less | LessEqual
+                ) =>
+                {
+                    let source = |e: &Expression| e.accept(&SourcePrinter);
+                    return Err(Self::error(
+                        operator,
+                        format!(
+                            "Comparisons cannot be chained; use '{} {} {} and {} {} {}' instead.",
+                            source(first),
+                            first_operator.lexeme(),
+                            source(middle),
+                            source(middle),
+                            operator.lexeme(),
+                            source(&right)
+                        ),
+                    ));
+                }
+                _ => (),
+            }
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: operator.clone(),
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_or(&self) -> ParseResult<Expression> {
+        let mut expr = self.bit_xor()?;
+
+        while self.matches(&[Pipe]) {
+            let operator = self.previous();
+            let right = self.bit_xor()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: operator.clone(),
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_xor(&self) -> ParseResult<Expression> {
+        let mut expr = self.bit_and()?;
+
+        while self.matches(&[Caret]) {
+            let operator = self.previous();
+            let right = self.bit_and()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: operator.clone(),
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_and(&self) -> ParseResult<Expression> {
+        let mut expr = self.shift()?;
+
+        while self.matches(&[Ampersand]) {
+            let operator = self.previous();
+            let right = self.shift()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: operator.clone(),
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&self) -> ParseResult<Expression> {
+        let mut expr = self.term()?;
+
+        while self.matches(&[LessLess, GreaterGreater]) {
             let operator = self.previous();
             let right = self.term()?;
             expr = Expression::Binary {
@@ -163,10 +488,10 @@ impl Parser {
     }
 
     fn factor(&self) -> ParseResult<Expression> {
-        let mut expr = self.unary()?;
+        let mut expr = self.power()?;
         while self.matches(&[Slash, Star]) {
             let operator = self.previous();
-            let right = self.unary()?;
+            let right = self.power()?;
             expr = Expression::Binary {
                 left: Box::new(expr),
                 operator: operator.clone(),
@@ -177,31 +502,66 @@ impl Parser {
         Ok(expr)
     }
 
-    fn unary(&self) -> ParseResult<Expression> {
-        if self.matches(&[Bang, Minus]) {
-            let op = self.previous();
-            let right = self.unary()?;
+    // 右结合：2 ** 3 ** 2 == 2 ** (3 ** 2)
+    fn power(&self) -> ParseResult<Expression> {
+        let expr = self.unary()?;
 
-            return Ok(Expression::Unary {
-                operator: op.clone(),
+        if self.matches(&[StarStar]) {
+            let operator = self.previous();
+            let right = self.nested(|| self.power())?;
+            return Ok(Expression::Binary {
+                left: Box::new(expr),
+                operator: operator.clone(),
                 right: Box::new(right),
             });
         }
 
-        self.primary()
+        Ok(expr)
     }
 
-    fn primary(&self) -> ParseResult<Expression> {
-        if self.matches(&[False]) {
-            return Ok(Expression::Literal {
-                value: ExprLiteral::Bool(false),
-            });
+    // 括号、列表、下标等嵌套都会经过 unary，所以在这里统一限制深度
+    fn unary(&self) -> ParseResult<Expression> {
+        self.nested(|| {
+            if self.matches(&[Bang, Minus]) {
+                let op = self.previous();
+                let right = self.unary()?;
+
+                return Ok(Expression::Unary {
+                    operator: op.clone(),
+                    right: Box::new(right),
+                });
+            }
+
+            self.call()
+        })
+    }
+
+    fn call(&self) -> ParseResult<Expression> {
+        let mut expr = self.primary()?;
+
+        while self.matches(&[LeftBracket]) {
+            let index = self.expression()?;
+            let bracket = self.consume(RightBracket, "Expect ']' after index.")?;
+            expr = Expression::Index {
+                object: Box::new(expr),
+                bracket: bracket.clone(),
+                index: Box::new(index),
+            }
         }
 
-        if self.matches(&[True]) {
-            return Ok(Expression::Literal {
-                value: ExprLiteral::Bool(true),
-            });
+        Ok(expr)
+    }
+
+    fn primary(&self) -> ParseResult<Expression> {
+        if self.matches(&[False, True]) {
+            let value = match self.previous().literal() {
+                Literal::Bool(b) => ExprLiteral::Bool(*b),
+                _ => {
+                    return Err(Self::error(self.peek(), "error parsing Boolean"));
+                }
+            };
+
+            return Ok(Expression::Literal { value });
         }
 
         if self.matches(&[Nil]) {
@@ -211,21 +571,20 @@ impl Parser {
         }
 
         if self.matches(&[Number]) {
-            let val = match self.previous().literal() {
-                Literal::Number(i) => *i,
+            let value = match self.previous().literal() {
+                Literal::Int(i) => ExprLiteral::Int(*i),
+                Literal::Number(n) => ExprLiteral::Number(*n),
                 _ => {
                     return Err(Self::error(self.peek(), "error parsing Number"));
                 }
             };
 
-            return Ok(Expression::Literal {
-                value: ExprLiteral::Number(val),
-            });
+            return Ok(Expression::Literal { value });
         }
 
         if self.matches(&[String]) {
             let val = match self.previous().literal() {
-                Literal::String(i) => i.clone(),
+                Literal::String(i) => i.to_string(),
                 _ => {
                     return Err(Self::error(self.peek(), "error parsing Strings"));
                 }
@@ -244,20 +603,105 @@ impl Parser {
             });
         }
 
-        Err(Self::error(self.peek(), "unexpected token"))
+        if self.matches(&[LeftBracket]) {
+            return self.array();
+        }
+
+        // 目前没有代码块，表达式中的 '{' 一定是 map
+        if self.matches(&[LeftBrace]) {
+            return self.map();
+        }
+
+        if self.matches(&[Interpolation]) {
+            return self.interpolation();
+        }
+
+        // 不消耗出错的 token，交给 synchronize 跳过，后面的表达式仍然可以继续解析
+        Err(Self::error(self.peek(), "Expect expression."))
+    }
+
+    // 左方括号已经被消耗，允许空列表和末尾多余的逗号
+    fn array(&self) -> ParseResult<Expression> {
+        let mut elements = vec![];
+
+        while !self.check(RightBracket) {
+            elements.push(self.expression()?);
+            if !self.matches(&[Comma]) {
+                break;
+            }
+        }
+        self.consume(RightBracket, "Expect ']' after list elements.")?;
+
+        Ok(Expression::Array { elements })
+    }
+
+    // 左花括号已经被消耗，与列表一样允许空 map 和末尾多余的逗号
+    fn map(&self) -> ParseResult<Expression> {
+        let brace = self.previous().clone();
+        let mut entries = vec![];
+
+        while !self.check(RightBrace) {
+            let key = self.expression()?;
+            self.consume(Colon, "Expect ':' after map key.")?;
+            entries.push((key, self.expression()?));
+            if !self.matches(&[Comma]) {
+                break;
+            }
+        }
+        self.consume(RightBrace, "Expect '}' after map entries.")?;
+
+        Ok(Expression::Map { brace, entries })
+    }
+
+    // 第一个 Interpolation token 已经被消耗
+    // "a ${b} c" 被扫描为 Interpolation("a ") b String(" c")
+    fn interpolation(&self) -> ParseResult<Expression> {
+        let mut parts = vec![];
+
+        loop {
+            Self::push_string_part(&mut parts, self.previous());
+            parts.push(self.expression()?);
+
+            if !self.matches(&[Interpolation]) {
+                break;
+            }
+        }
+        let tail = self.consume(String, "Expect '}' after interpolated expression.")?;
+        Self::push_string_part(&mut parts, tail);
+
+        Ok(Expression::Interpolation { parts })
+    }
+
+    // 空的字符串片段不需要出现在 AST 中
+    fn push_string_part(parts: &mut Vec<Expression>, token: &Token) {
+        if let Literal::String(s) = token.literal() {
+            if !s.as_str().is_empty() {
+                parts.push(Expression::Literal {
+                    value: ExprLiteral::String(s.to_string()),
+                });
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::expression::ast_printer::AstPrinter;
+    use crate::expression::interpreter::Interpreter;
+    use crate::expression::{ExprLiteral, Expression, MAX_DEPTH};
+    use crate::optimizer::fold_constants;
     use crate::parser::Parser;
     use crate::scanner::Scanner;
+    use crate::token::{Literal, Token, TokenType};
 
     fn compile_to_ast(source_code: &str) -> String {
         let tokens = Scanner::parse(source_code);
-        let expr = Parser::parse(tokens);
-        expr.accept(&AstPrinter)
+        let program = Parser::parse(tokens);
+        program
+            .iter()
+            .map(|expr| expr.accept(&AstPrinter))
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 
     #[test]
@@ -275,4 +719,272 @@ mod tests {
 
         println!("{}", compile_to_ast("123 + \"123\" != \"123123\";"));
     }
+
+    #[test]
+    fn test_array() {
+        assert_eq!(compile_to_ast("[];"), "(list)");
+        assert_eq!(compile_to_ast("[1, 2 + 3,];"), "(list 1 (+ 2 3))");
+        assert_eq!(compile_to_ast("[[1], []];"), "(list (list 1) (list))");
+    }
+
+    #[test]
+    fn test_map() {
+        assert_eq!(compile_to_ast("{};"), "(map)");
+        assert_eq!(
+            compile_to_ast("{\"a\": 1, 2: [3],};"),
+            "(map (a 1) (2 (list 3)))"
+        );
+        assert_eq!(
+            compile_to_ast("{true ? 1 : 2: 3}[1];"),
+            "(index (map ((?: true 1 2) 3)) 1)"
+        );
+
+        let errors = Parser::parse_program(Scanner::parse("{1 2}; {1: 2;")).unwrap_err();
+        assert_eq!(errors[0].message, "Expect ':' after map key.");
+        assert_eq!(errors[1].message, "Expect '}' after map entries.");
+    }
+
+    #[test]
+    fn test_index() {
+        assert_eq!(compile_to_ast("[1, 2][0];"), "(index (list 1 2) 0)");
+        assert_eq!(
+            compile_to_ast("[[1]][0][0] = 2 + 3;"),
+            "(set-index (index (list (list 1)) 0) 0 (+ 2 3))"
+        );
+    }
+
+    #[test]
+    fn test_program() {
+        assert_eq!(compile_to_ast("1; 2 + 3; [4]"), "1 (+ 2 3) (list 4)");
+        assert_eq!(compile_to_ast(""), "");
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let tokens = Scanner::parse("1 +; (2; 3; 4 5;");
+        let errors = Parser::parse_program(tokens).unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].lexeme(), ";");
+        assert_eq!(errors[1].message, "Expect ')' after expression.");
+        assert_eq!(errors[2].lexeme(), "5");
+        assert_eq!(errors[2].message, "Expect ';' after expression.");
+        assert!(errors.iter().all(|e| e.line() == 1));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eq!(
+            compile_to_ast("[1][0] += 2;"),
+            "(set-index (list 1) 0 (+= 2))"
+        );
+        assert_eq!(
+            compile_to_ast("[1][0] /= 2 * 3;"),
+            "(set-index (list 1) 0 (/= (* 2 3)))"
+        );
+
+        let errors = Parser::parse_program(Scanner::parse("1 += 2;")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].lexeme(), "+=");
+        assert_eq!(errors[0].message, "Invalid assignment target.");
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let expr = Parser::parse_expression(Scanner::parse("1 + 2")).unwrap();
+        assert_eq!(expr.accept(&AstPrinter), "(+ 1 2)");
+
+        let err = Parser::parse_expression(Scanner::parse("1 + 2 3")).unwrap_err();
+        assert_eq!(err.lexeme(), "3");
+        assert_eq!(err.message, "Expect end of expression.");
+
+        // 分号也算多余的 token
+        assert!(Parser::parse_expression(Scanner::parse("1 + 2;")).is_err());
+        assert!(Parser::parse_expression(Scanner::parse("")).is_err());
+    }
+
+    #[test]
+    fn test_missing_expression() {
+        let errors = Parser::parse_program(Scanner::parse("1 + ;\n(;\n2;")).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].lexeme(), ";");
+        assert_eq!(errors[0].token.line(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error at ';': Expect expression."
+        );
+        assert_eq!(errors[1].lexeme(), ";");
+        assert_eq!(errors[1].token.line(), 2);
+
+        // 出错之后恢复，后面正确的表达式不会报错
+        let errors = Parser::parse_program(Scanner::parse("1 + ;\n2;")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        let errors = Parser::parse_program(Scanner::parse("1 < 2 < 3;")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].token.start(), 6);
+        assert_eq!(
+            errors[0].message,
+            "Comparisons cannot be chained; use '1 < 2 and 2 < 3' instead."
+        );
+
+        // 加了括号就是用户明确想要的，不报错
+        assert_eq!(compile_to_ast("(1 < 2) < 3;"), "(< (group (< 1 2)) 3)");
+        assert_eq!(compile_to_ast("1 < 2 == 2 > 1;"), "(== (< 1 2) (> 2 1))");
+        assert_eq!(compile_to_ast("1 + 2 < 3;"), "(< (+ 1 2) 3)");
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_eq!(
+            compile_to_ast("\"Hello, ${\"world\"}!\";"),
+            "(interpolate Hello,  world !)"
+        );
+        assert_eq!(
+            compile_to_ast("\"${1}${2 + 3}\";"),
+            "(interpolate 1 (+ 2 3))"
+        );
+    }
+
+    #[test]
+    fn test_bitwise_precedence() {
+        assert_eq!(
+            compile_to_ast("1 | 2 ^ 3 & 4 << 1 + 1;"),
+            "(| 1 (^ 2 (& 3 (<< 4 (+ 1 1)))))"
+        );
+        assert_eq!(compile_to_ast("1 < 2 | 3;"), "(< 1 (| 2 3))");
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(compile_to_ast("2 ** 3 ** 2;"), "(** 2 (** 3 2))");
+        assert_eq!(compile_to_ast("2 * 3 ** 2;"), "(* 2 (** 3 2))");
+        assert_eq!(compile_to_ast("-2 ** 2;"), "(** (- 2) 2)");
+    }
+
+    #[test]
+    fn test_logical() {
+        assert_eq!(
+            compile_to_ast("1 or 2 and 3 == 3;"),
+            "(or 1 (and 2 (== 3 3)))"
+        );
+        assert_eq!(
+            compile_to_ast("nil or false ? 1 : 2;"),
+            "(?: (or nil false) 1 2)"
+        );
+        assert_eq!(
+            compile_to_ast("nil ?? false ?? 1 and 2;"),
+            "(?? (?? nil false) (and 1 2))"
+        );
+        assert_eq!(compile_to_ast("nil ?? 1 ? 2 : 3;"), "(?: (?? nil 1) 2 3)");
+    }
+
+    #[test]
+    fn test_ternary() {
+        assert_eq!(compile_to_ast("true ? 1 : 2;"), "(?: true 1 2)");
+        assert_eq!(
+            compile_to_ast("1 > 2 ? 3 : 4 == 4 ? 5 : 6;"),
+            "(?: (> 1 2) 3 (?: (== 4 4) 5 6))"
+        );
+    }
+
+    #[test]
+    fn test_too_deeply_nested() {
+        let nested = |depth: usize| format!("{}1{};", "(".repeat(depth), ")".repeat(depth));
+
+        let errors = Parser::parse_program(Scanner::parse(nested(MAX_DEPTH))).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Expression too deeply nested.");
+
+        // 限制以内的深层嵌套可以正常解析、折叠、求值和输出，即使测试线程的栈很小
+        let program = Parser::parse(Scanner::parse(nested(MAX_DEPTH - 2)));
+        assert_eq!(
+            program[0]
+                .accept(&Interpreter::new())
+                .unwrap()
+                .into_string(),
+            "1"
+        );
+        assert!(program[0].accept(&AstPrinter).starts_with("(group (group"));
+        let folded = fold_constants(program.into_iter().next().unwrap());
+        assert_eq!(folded.accept(&AstPrinter), "1");
+
+        let tokens = Scanner::parse("-(-(-1));");
+        assert!(Parser::new(tokens).with_max_depth(4).program().is_err());
+        let tokens = Scanner::parse("-(-(-1));");
+        assert!(Parser::new(tokens).with_max_depth(6).program().is_ok());
+    }
+
+    #[test]
+    fn test_bool_literal() {
+        let tokens = Scanner::parse("true; false;");
+        assert_eq!(tokens[0].token_type(), TokenType::True);
+        assert_eq!(tokens[0].literal(), &Literal::Bool(true));
+        assert_eq!(tokens[2].literal(), &Literal::Bool(false));
+
+        let program = Parser::parse(tokens);
+        assert_eq!(
+            program,
+            [
+                Expression::Literal {
+                    value: ExprLiteral::Bool(true)
+                },
+                Expression::Literal {
+                    value: ExprLiteral::Bool(false)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_degenerate_tokens() {
+        let token = |token_type: TokenType, lexeme: &str, literal: Literal| {
+            Token::new(token_type, lexeme, literal, 1, 0)
+        };
+
+        assert_eq!(Parser::parse_program(vec![]).unwrap(), []);
+        assert_eq!(Parser::new(vec![]).previous().token_type(), TokenType::EOF);
+
+        // 缺少 EOF
+        let program = Parser::parse_program(vec![token(TokenType::Number, "1", Literal::Int(1))]);
+        assert_eq!(program.unwrap().len(), 1);
+        let errors = Parser::parse_program(vec![
+            token(TokenType::Minus, "-", Literal::None),
+            token(TokenType::LeftParen, "(", Literal::None),
+        ])
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+
+        // 字面量和 token 类型不匹配
+        let errors =
+            Parser::parse_program(vec![token(TokenType::Number, "1", Literal::None)]).unwrap_err();
+        assert_eq!(errors[0].message, "error parsing Number");
+        assert!(
+            Parser::parse_program(vec![token(TokenType::EOF, "", Literal::None); 3])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let errors = Parser::parse_program(Scanner::parse("1;\n(1 + 2")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line(), 2);
+        assert_eq!(errors[0].message, "Expect ')' after expression.");
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2] Error at end: Expect ')' after expression."
+        );
+
+        let errors = Parser::parse_program(Scanner::parse("(1 + 2;")).unwrap_err();
+        assert_eq!(errors[0].lexeme(), ";");
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error at ';': Expect ')' after expression."
+        );
+    }
 }
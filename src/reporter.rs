@@ -1,30 +1,185 @@
+use crate::error::LoxError;
 use crate::expression::interpreter::RuntimeError;
+use crate::parser::ParseError;
+use crate::scanner::ScanError;
+use crate::source_map::SourceMap;
 use crate::token::{Token, TokenType};
+use std::cell::{Cell, RefCell};
 use std::fmt::Display;
-use std::process;
+use std::io::IsTerminal;
+use std::{env, io, process};
+
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+thread_local! {
+    // 当前正在运行的源码，用来从 token 的偏移量推导出行号和列号
+    static SOURCE_MAP: RefCell<Option<SourceMap>> = const { RefCell::new(None) };
+    // 输出到终端并且没有设置 NO_COLOR 时才使用 ANSI 颜色
+    static COLORS: Cell<bool> =
+        Cell::new(env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal());
+}
 
 #[inline]
-pub fn error_at_line(line: usize, message: impl Display) {
-    report(line, "", message)
+pub fn set_colors(enabled: bool) {
+    COLORS.with(|colors| colors.set(enabled));
 }
 
 #[inline]
-pub fn error_at_token(token: &Token, message: impl Display) {
-    if token.token_type() == TokenType::EOF {
-        report(token.line(), " at end", message);
+fn colors() -> bool {
+    COLORS.with(|colors| colors.get())
+}
+
+// 用红色标出错误的位置前缀和 ^~~~
+fn paint(text: impl Display, colors: bool) -> String {
+    if colors {
+        format!("{RED}{text}{RESET}")
     } else {
-        report(token.line(), format!(" at '{}'", token.lexeme()), message);
+        text.to_string()
     }
 }
 
 #[inline]
-pub fn runtime_error(error: RuntimeError) {
-    println!("[line {}] RuntimeError {}", error.token.line(), error.msg);
-    process::exit(70);
+pub fn set_source(source: &str) {
+    SOURCE_MAP.with(|map| *map.borrow_mut() = Some(SourceMap::new(source)));
+}
+
+// 有源码时输出行号、列号，并在出错的源码行下面用 ^~~~ 标出 token，例如：
+// [line 2, column 3] Error at '-': message
+// 2 |   -"a";
+//   |   ^
+fn format_at_token(
+    map: Option<&SourceMap>,
+    token: &Token,
+    message: impl Display,
+    colors: bool,
+) -> String {
+    let Some(map) = map else {
+        let location = paint(format!("[line {}]", token.line()), colors);
+        return format!("{location} {message}");
+    };
+
+    let (line, column) = map.offset_to_line_col(token.start());
+    let location = paint(format!("[line {line}, column {column}]"), colors);
+    let mut out = format!("{location} {message}");
+
+    if let Some(text) = map.line_text(line) {
+        let text = map.expand_tabs(text);
+        let gutter = line.to_string();
+        // 跨行的 token（多行字符串）只标到行尾
+        let rest = text.chars().count().saturating_sub(column - 1);
+        let width = token.lexeme().chars().count().min(rest).max(1);
+
+        out += &format!(
+            "\n{gutter} | {text}\n{} | {}{}",
+            " ".repeat(gutter.len()),
+            " ".repeat(column - 1),
+            paint(format!("^{}", "~".repeat(width - 1)), colors)
+        );
+    }
+
+    out
+}
+
+fn at_token(token: &Token, message: impl Display) -> String {
+    SOURCE_MAP.with(|map| format_at_token(map.borrow().as_ref(), token, message, colors()))
+}
+
+fn report_scan_errors(errors: &[ScanError]) {
+    for error in errors {
+        let prefix = paint(format!("[line {}] Error:", error.line), colors());
+        println!("{prefix} {}", error.msg);
+    }
+}
+
+// 一次性报告所有词法错误后退出
+pub fn scan_errors(errors: &[ScanError]) -> ! {
+    report_scan_errors(errors);
+    process::exit(65);
 }
 
 #[inline]
-pub fn report(line: usize, wheres: impl Display, message: impl Display) {
-    println!("[line {line}] Error {wheres}: {message}");
+pub fn error_at_token(token: &Token, message: impl Display) {
+    let message = if token.token_type() == TokenType::EOF {
+        format!("Error at end: {message}")
+    } else {
+        format!("Error at '{}': {message}", token.lexeme())
+    };
+
+    println!("{}", at_token(token, message));
+}
+
+fn report_parse_errors(errors: &[ParseError]) {
+    for error in errors {
+        error_at_token(&error.token, &error.message);
+    }
+}
+
+// 一次性报告所有语法错误后退出
+pub fn parse_errors(errors: &[ParseError]) -> ! {
+    report_parse_errors(errors);
     process::exit(65);
 }
+
+#[inline]
+fn report_runtime_error(error: &RuntimeError) {
+    println!(
+        "{}",
+        at_token(&error.token, format!("RuntimeError {error}"))
+    );
+}
+
+// 只输出错误信息，是否退出以及退出码由调用方决定
+pub fn report(error: &LoxError) {
+    match error {
+        LoxError::Scan(errors) => report_scan_errors(errors),
+        LoxError::Parse(errors) => report_parse_errors(errors),
+        LoxError::Runtime(error) => report_runtime_error(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn test_format_at_token() {
+        let source = "1 +\n  -\"abc\" * 2;";
+        let map = SourceMap::new(source);
+        let tokens = Scanner::parse(source);
+
+        assert_eq!(
+            format_at_token(Some(&map), &tokens[3], "RuntimeError message", false),
+            "[line 2, column 4] RuntimeError message\n\
+             2 |   -\"abc\" * 2;\n  \
+               |    ^~~~~"
+        );
+        assert_eq!(
+            format_at_token(None, &tokens[3], "RuntimeError message", false),
+            "[line 2] RuntimeError message"
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let source = "-\"abc\";";
+        let map = SourceMap::new(source);
+        let tokens = Scanner::parse(source);
+
+        let plain = format_at_token(Some(&map), &tokens[0], "message", false);
+        assert!(!plain.contains('\x1b'));
+
+        let colored = format_at_token(Some(&map), &tokens[0], "message", true);
+        assert_eq!(
+            colored,
+            "\x1b[31m[line 1, column 1]\x1b[0m message\n\
+             1 | -\"abc\";\n  \
+               | \x1b[31m^\x1b[0m"
+        );
+        assert_eq!(
+            format_at_token(None, &tokens[0], "message", true),
+            "\x1b[31m[line 1]\x1b[0m message"
+        );
+    }
+}
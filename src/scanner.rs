@@ -1,4 +1,6 @@
+use crate::interner::Interner;
 use crate::reporter;
+use crate::source_map::{SourceMap, TAB_WIDTH};
 use crate::token::{Literal, Token, TokenType};
 use lazy_static::lazy_static;
 use std::collections::HashMap;
@@ -28,100 +30,224 @@ lazy_static! {
     };
 }
 
+const UNTERMINATED_STRING: &str = "Unterminated String";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub line: usize,
+    pub offset: usize, // 出错的 token 在源码中的起始字节偏移量，通过 SourceMap 换算成列号
+    pub msg: String,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Scanner {
-    source: String,
-    tokens: Vec<Token>,
+    source: String,     // 源码，start、current 和 token 的偏移量都是字节偏移量
+    tokens: Vec<Token>, // 已经扫描出来但还没有被取走的 token
     start: usize,
     current: usize,
     line: usize,
+    finished: bool,             // 是否已经产生了 EOF token
+    interpolations: Vec<usize>, // 每一层未结束的 "${" 中尚未闭合的 '{' 数量
+    errors: Vec<ScanError>,     // 扫描过程中遇到的错误，不会中断扫描
+    token_count: usize,         // 已经产生的 token 数量（不含 EOF）
+    max_tokens: usize,          // 最多产生多少个 token，超过之后报错并停止扫描
+    keep_comments: bool,        // 是否把注释作为 Comment token 输出，给格式化工具使用
+    interner: Interner,         // lexeme 和字符串字面量的驻留表，重复出现的字符串共享同一块内存
+    tab_width: usize,           // 制表符宽度，由这个 Scanner 创建的 SourceMap 按它换算列号
 }
 
 impl Scanner {
-    pub(crate) fn new(source: impl ToString) -> Self {
+    pub fn new(source: impl ToString) -> Self {
         Scanner {
             source: source.to_string(),
             tokens: vec![],
             start: 0,
             current: 0,
             line: 1,
+            finished: false,
+            interpolations: vec![],
+            errors: vec![],
+            token_count: 0,
+            max_tokens: usize::MAX,
+            keep_comments: false,
+            interner: Interner::new(),
+            tab_width: TAB_WIDTH,
         }
     }
 
-    pub(crate) fn scan_tokens(&mut self) {
-        loop {
-            self.start = self.current;
-            if let Some(c) = self.next_char() {
-                match c {
-                    '(' => self.add_token(TokenType::LeftParen, Literal::None),
-                    ')' => self.add_token(TokenType::RightParen, Literal::None),
-                    '{' => self.add_token(TokenType::LeftBrace, Literal::None),
-                    '}' => self.add_token(TokenType::RightBrace, Literal::None),
-                    ',' => self.add_token(TokenType::Comma, Literal::None),
-                    '.' => self.add_token(TokenType::Dot, Literal::None),
-                    '-' => self.add_token(TokenType::Minus, Literal::None),
-                    '+' => self.add_token(TokenType::Plus, Literal::None),
-                    ';' => self.add_token(TokenType::Semicolon, Literal::None),
-                    '*' => self.add_token(TokenType::Star, Literal::None),
-                    '!' => {
-                        if self.next_char_matches('=') {
-                            self.add_token(TokenType::BangEqual, Literal::None);
-                        } else {
-                            self.add_token(TokenType::Bang, Literal::None);
-                        }
-                    }
-                    '=' => {
-                        if self.next_char_matches('=') {
-                            self.add_token(TokenType::EqualEqual, Literal::None);
-                        } else {
-                            self.add_token(TokenType::Equal, Literal::None);
-                        }
-                    }
-                    '<' => {
-                        if self.next_char_matches('=') {
-                            self.add_token(TokenType::LessEqual, Literal::None);
-                        } else {
-                            self.add_token(TokenType::Less, Literal::None);
-                        }
-                    }
-                    '>' => {
-                        if self.next_char_matches('=') {
-                            self.add_token(TokenType::GreaterEqual, Literal::None);
-                        } else {
-                            self.add_token(TokenType::Greater, Literal::None);
-                        }
+    // 到目前为止遇到的词法错误，出错的字符会被跳过，已经产生的 token 仍然可以使用
+    #[inline]
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    // 限制 token 的数量，用于运行不受信任的代码，默认没有限制
+    #[inline]
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    // 保留 "//" 注释，产生的 Comment token 的 literal 是去掉 "//" 之后的注释内容
+    #[inline]
+    pub fn with_keep_comments(mut self, keep_comments: bool) -> Self {
+        self.keep_comments = keep_comments;
+        self
+    }
+
+    // 换算列号时制表符的宽度，默认是 8
+    #[inline]
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    // 按照这个 Scanner 的源码和制表符宽度创建 SourceMap
+    pub fn source_map(&self) -> SourceMap {
+        SourceMap::new(&self.source).with_tab_width(self.tab_width)
+    }
+
+    // 扫描一个 lexeme，源码已经全部扫描完时返回 false
+    fn scan_token(&mut self) -> bool {
+        self.start = self.current;
+        let Some(c) = self.next_char() else {
+            return false;
+        };
+
+        match c {
+            '(' => self.add_token(TokenType::LeftParen, Literal::None),
+            ')' => self.add_token(TokenType::RightParen, Literal::None),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(TokenType::LeftBrace, Literal::None);
+            }
+            '}' => match self.interpolations.last_mut() {
+                // 插值表达式结束，继续扫描字符串剩下的部分
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.string();
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.add_token(TokenType::RightBrace, Literal::None);
+                }
+                None => self.add_token(TokenType::RightBrace, Literal::None),
+            },
+            '[' => self.add_token(TokenType::LeftBracket, Literal::None),
+            ']' => self.add_token(TokenType::RightBracket, Literal::None),
+            ',' => self.add_token(TokenType::Comma, Literal::None),
+            // .5 等价于 0.5
+            '.' if self.peek().is_some_and(Self::is_digit) => self.number(),
+            '.' => self.add_token(TokenType::Dot, Literal::None),
+            ';' => self.add_token(TokenType::Semicolon, Literal::None),
+            '?' => {
+                if self.next_char_matches('?') {
+                    self.add_token(TokenType::QuestionQuestion, Literal::None);
+                } else {
+                    self.add_token(TokenType::Question, Literal::None);
+                }
+            }
+            ':' => self.add_token(TokenType::Colon, Literal::None),
+            '&' => self.add_token(TokenType::Ampersand, Literal::None),
+            '|' => self.add_token(TokenType::Pipe, Literal::None),
+            '^' => self.add_token(TokenType::Caret, Literal::None),
+            '-' => {
+                if self.next_char_matches('=') {
+                    self.add_token(TokenType::MinusEqual, Literal::None);
+                } else {
+                    self.add_token(TokenType::Minus, Literal::None);
+                }
+            }
+            '+' => {
+                if self.next_char_matches('=') {
+                    self.add_token(TokenType::PlusEqual, Literal::None);
+                } else {
+                    self.add_token(TokenType::Plus, Literal::None);
+                }
+            }
+            '*' => {
+                if self.next_char_matches('=') {
+                    self.add_token(TokenType::StarEqual, Literal::None);
+                } else if self.next_char_matches('*') {
+                    self.add_token(TokenType::StarStar, Literal::None);
+                } else {
+                    self.add_token(TokenType::Star, Literal::None);
+                }
+            }
+            '!' => {
+                if self.next_char_matches('=') {
+                    self.add_token(TokenType::BangEqual, Literal::None);
+                } else {
+                    self.add_token(TokenType::Bang, Literal::None);
+                }
+            }
+            '=' => {
+                if self.next_char_matches('=') {
+                    self.add_token(TokenType::EqualEqual, Literal::None);
+                } else {
+                    self.add_token(TokenType::Equal, Literal::None);
+                }
+            }
+            '<' => {
+                if self.next_char_matches('=') {
+                    self.add_token(TokenType::LessEqual, Literal::None);
+                } else if self.next_char_matches('<') {
+                    self.add_token(TokenType::LessLess, Literal::None);
+                } else {
+                    self.add_token(TokenType::Less, Literal::None);
+                }
+            }
+            '>' => {
+                if self.next_char_matches('=') {
+                    self.add_token(TokenType::GreaterEqual, Literal::None);
+                } else if self.next_char_matches('>') {
+                    self.add_token(TokenType::GreaterGreater, Literal::None);
+                } else {
+                    self.add_token(TokenType::Greater, Literal::None);
+                }
+            }
+            '/' => {
+                if self.next_char_matches('/') {
+                    while self.peek().is_some_and(|c| c != '\n' && c != '\r') {
+                        self.next_char();
                     }
-                    '/' => {
-                        if self.next_char_matches('/') {
-                            while self.peek().is_some() && self.peek() != Some('\n') {
-                                self.next_char();
-                            }
-                        } else {
-                            self.add_token(TokenType::Slash, Literal::None);
-                        }
+                    if self.keep_comments {
+                        let text = &self.source[self.start + 2..self.current];
+                        let literal = Literal::String(self.interner.intern(text));
+                        self.add_token(TokenType::Comment, literal);
                     }
+                } else if self.next_char_matches('=') {
+                    self.add_token(TokenType::SlashEqual, Literal::None);
+                } else {
+                    self.add_token(TokenType::Slash, Literal::None);
+                }
+            }
 
-                    ' ' | '\r' | '\t' => (),
-                    '\n' => self.line += 1,
-
-                    '"' => self.string(),
-                    c => {
-                        if Self::is_digit(c) {
-                            self.number();
-                        } else if Self::is_alpha(c) {
-                            self.identifier();
-                        } else {
-                            reporter::error_at_line(self.line, "Unexpected character.");
-                        }
-                    }
+            // 第一行的 "#!" 是 shebang，当作注释跳过
+            '#' if self.start == 0 && self.peek() == Some('!') => {
+                while self.peek().is_some_and(|c| c != '\n' && c != '\r') {
+                    self.next_char();
+                }
+            }
+
+            c if self.is_line_break(c) => self.line += 1,
+            ' ' | '\r' | '\t' => (),
+
+            '"' => self.string(),
+            c => {
+                if Self::is_digit(c) {
+                    self.number();
+                } else if Self::is_alpha(c) {
+                    self.identifier();
+                } else {
+                    self.error("Unexpected character.");
                 }
-            } else {
-                break;
             }
         }
 
-        self.tokens
-            .push(Token::new(TokenType::EOF, "", Literal::None, self.line));
+        true
     }
 
     #[inline]
@@ -144,18 +270,23 @@ impl Scanner {
             self.next_char();
         }
 
-        let text = &self.source[self.start..self.current];
-        let token_type = KEYWORDS.get(text);
+        let token_type = KEYWORDS.get(&self.source[self.start..self.current]);
 
         if let Some(token_type) = token_type {
-            self.add_token(*token_type, Literal::None);
+            // true 和 false 与字符串、数字一样带上字面量的值
+            let literal = match token_type {
+                TokenType::True => Literal::Bool(true),
+                TokenType::False => Literal::Bool(false),
+                _ => Literal::None,
+            };
+            self.add_token(*token_type, literal);
         } else {
             self.add_token(TokenType::Identifier, Literal::None);
         }
     }
 
     fn number(&mut self) {
-        while Self::is_digit(self.peek().unwrap()) {
+        while self.peek().is_some() && Self::is_digit(self.peek().unwrap()) {
             self.next_char();
         }
         if self.peek() == Some('.')
@@ -163,39 +294,84 @@ impl Scanner {
             && Self::is_digit(self.peek_next().unwrap())
         {
             self.next_char();
-            while Self::is_digit(self.peek().unwrap()) {
+            while self.peek().is_some() && Self::is_digit(self.peek().unwrap()) {
                 self.next_char();
             }
+        } else if self.peek() == Some('.') && !self.peek_next().is_some_and(Self::is_alpha) {
+            // 5. 后面跟着字母时是属性访问，其余情况都是写了一半的小数
+            self.next_char();
+            self.error("Expect digit after '.' in number literal.");
+            return;
         }
-        let val = &self.source[self.start..self.current];
-        let val = val.parse::<f64>();
-        if val.is_err() {
-            reporter::error_at_line(self.line, "error parsing number");
-        }
-        self.add_token(TokenType::Number, Literal::Number(val.unwrap()))
+
+        // 没有小数点的是整数，超出 i64 范围时报错，而不是悄悄变成不精确的浮点数
+        let text = &self.source[self.start..self.current];
+        let literal = if text.contains('.') {
+            match text.parse::<f64>() {
+                Ok(n) => Literal::Number(n),
+                Err(_) => return self.error("error parsing number"),
+            }
+        } else {
+            match text.parse::<i64>() {
+                Ok(i) => Literal::Int(i),
+                Err(_) => return self.error("Integer literal out of range."),
+            }
+        };
+        self.add_token(TokenType::Number, literal)
     }
 
+    // 扫描字符串直到结束的 '"'，或者遇到 "${" 时产生一个 Interpolation token
+    // "\$" 会被转义成 '$'
     fn string(&mut self) {
-        while self.peek().is_some() && self.peek().unwrap() != '"' {
-            if self.peek() == Some('\n') {
-                self.line += 1
+        let mut value = String::new();
+        // 多行字符串没有结束时，报告字符串开始的那一行
+        let start_line = self.line;
+
+        loop {
+            match self.peek() {
+                None => {
+                    self.error_at_line(start_line, UNTERMINATED_STRING);
+                    return;
+                }
+                Some('"') => {
+                    self.next_char();
+                    let literal = self.string_literal(&value);
+                    self.add_token(TokenType::String, literal);
+                    return;
+                }
+                Some('\\') if self.peek_next() == Some('$') => {
+                    self.current += 2;
+                    value.push('$');
+                }
+                Some('$') if self.peek_next() == Some('{') => {
+                    self.current += 2;
+                    let literal = self.string_literal(&value);
+                    self.add_token(TokenType::Interpolation, literal);
+                    self.interpolations.push(0);
+                    return;
+                }
+                Some(c) => {
+                    self.next_char();
+                    if self.is_line_break(c) {
+                        self.line += 1;
+                    }
+                    value.push(c);
+                }
             }
-            self.next_char();
         }
-        if self.peek().is_none() {
-            reporter::error_at_line(self.line, "Unterminated String");
-            return;
-        }
-        self.next_char();
+    }
 
-        let value = &self.source[self.start + 1..self.current - 1];
-        self.add_token(TokenType::String, Literal::String(value.to_string()));
+    // c 是刚刚读入的字符；"\r\n"、"\n" 和单独的 "\r" 都只算一次换行
+    #[inline]
+    fn is_line_break(&self, c: char) -> bool {
+        c == '\n' || (c == '\r' && self.peek() != Some('\n'))
     }
 
+    // 每次前进一个完整的字符，所以偏移量总是落在字符边界上
     fn next_char(&mut self) -> Option<char> {
-        let c = self.source.chars().nth(self.current);
-        self.current += 1;
-        c
+        let c = self.peek()?;
+        self.current += c.len_utf8();
+        Some(c)
     }
 
     fn next_char_matches(&mut self, c: char) -> bool {
@@ -205,44 +381,138 @@ impl Scanner {
             return false;
         }
 
-        self.current += 1;
+        self.current += c.len_utf8();
 
         true
     }
 
     #[inline]
     fn peek(&self) -> Option<char> {
-        self.source.chars().nth(self.current)
+        self.source[self.current..].chars().next()
     }
 
     #[inline]
     fn peek_next(&self) -> Option<char> {
-        self.source.chars().nth(self.current + 1)
+        self.source[self.current..].chars().nth(1)
     }
 
     #[inline]
     fn add_token(&mut self, token_type: TokenType, literal: Literal) {
-        let text = &self.source[self.start..self.current];
-        self.tokens
-            .push(Token::new(token_type, text, literal, self.line));
+        let lexeme = self.interner.intern(&self.source[self.start..self.current]);
+        self.tokens.push(Token::new(
+            token_type, lexeme, literal, self.line, self.start,
+        ));
     }
 
     #[inline]
-    fn take_tokens(self) -> Vec<Token> {
-        self.tokens
+    fn string_literal(&mut self, value: &str) -> Literal {
+        Literal::String(self.interner.intern(value))
     }
 
     #[inline]
-    pub fn parse(source_code: impl ToString) -> Vec<Token> {
+    fn error(&mut self, msg: impl ToString) {
+        self.error_at_line(self.line, msg);
+    }
+
+    #[inline]
+    fn error_at_line(&mut self, line: usize, msg: impl ToString) {
+        self.errors.push(ScanError {
+            line,
+            offset: self.start,
+            msg: msg.to_string(),
+        });
+    }
+
+    // 扫描全部源码，返回所有的 token 或者所有的错误
+    pub fn scan(source_code: impl ToString) -> Result<Vec<Token>, Vec<ScanError>> {
+        Scanner::new(source_code).scan_all()
+    }
+
+    pub fn scan_all(mut self) -> Result<Vec<Token>, Vec<ScanError>> {
+        let tokens: Vec<_> = self.by_ref().collect();
+
+        if self.errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // 同时返回 token 和 SourceMap，方便工具根据偏移量查找行号、列号和源码。
+    // 与 scan_all 一样，出错时返回所有的错误，由调用方决定怎么报告
+    pub fn parse_with_source(source_code: &str) -> Result<(Vec<Token>, SourceMap), Vec<ScanError>> {
+        let scanner = Scanner::new(source_code);
+        let source_map = scanner.source_map();
+        Ok((scanner.scan_all()?, source_map))
+    }
+
+    // 给 REPL 使用：还有没闭合的括号、字符串或者插值时返回 false，需要继续读入下一行
+    pub fn is_complete(source_code: &str) -> bool {
         let mut scanner = Scanner::new(source_code);
-        scanner.scan_tokens();
-        scanner.take_tokens()
+        let mut depth = 0;
+
+        for token in scanner.by_ref() {
+            match token.token_type() {
+                TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                    depth -= 1
+                }
+                _ => (),
+            }
+        }
+
+        depth <= 0
+            && scanner.interpolations.is_empty()
+            && !scanner.errors.iter().any(|e| e.msg == UNTERMINATED_STRING)
+    }
+
+    // 给 main 使用的便捷方法，遇到词法错误时报告所有错误并退出
+    #[inline]
+    pub fn parse(source_code: impl ToString) -> Vec<Token> {
+        match Self::scan(source_code) {
+            Ok(tokens) => tokens,
+            Err(errors) => reporter::scan_errors(&errors),
+        }
+    }
+}
+
+impl Scanner {
+    #[inline]
+    fn eof(&mut self) -> Token {
+        self.finished = true;
+        Token::new(TokenType::EOF, "", Literal::None, self.line, self.start)
+    }
+}
+
+// 按需逐个产生 token，最后一个 token 一定是 EOF
+impl Iterator for Scanner {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        while self.tokens.is_empty() {
+            if !self.scan_token() {
+                return Some(self.eof());
+            }
+        }
+
+        if self.token_count >= self.max_tokens {
+            self.error(format!("Too many tokens (limit {}).", self.max_tokens));
+            return Some(self.eof());
+        }
+
+        self.token_count += 1;
+        Some(self.tokens.remove(0))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::eval_ok;
     use crate::token::TokenStream;
 
     #[test]
@@ -269,4 +539,247 @@ mod tests {
         .into();
         println!("{tokens:?}")
     }
+
+    #[test]
+    fn test_interpolation_tokens() {
+        let tokens = Scanner::parse("\"a ${ [1][0] } b ${\"c\"}\\${d}\"");
+        assert_eq!(
+            tokens.iter().map(|t| t.token_type()).collect::<Vec<_>>(),
+            [
+                TokenType::Interpolation,
+                TokenType::LeftBracket,
+                TokenType::Number,
+                TokenType::RightBracket,
+                TokenType::LeftBracket,
+                TokenType::Number,
+                TokenType::RightBracket,
+                TokenType::Interpolation,
+                TokenType::String,
+                TokenType::String,
+                TokenType::EOF,
+            ]
+        );
+        assert_eq!(tokens[0].literal(), &Literal::String("a ".into()));
+        assert_eq!(tokens[7].literal(), &Literal::String(" b ".into()));
+        assert_eq!(tokens[9].literal(), &Literal::String("${d}".into()));
+    }
+
+    #[test]
+    fn test_lazy_tokens() {
+        let mut scanner = Scanner::new("1 + 2 * 3;");
+        let tokens: Vec<_> = scanner.by_ref().take(3).collect();
+
+        assert_eq!(
+            tokens.iter().map(|t| t.lexeme()).collect::<Vec<_>>(),
+            ["1", "+", "2"]
+        );
+        // 只扫描到了 "1 + 2"
+        assert_eq!(scanner.current, 5);
+
+        let rest: Vec<_> = scanner.map(|t| t.token_type()).collect();
+        assert_eq!(
+            rest,
+            [
+                TokenType::Star,
+                TokenType::Number,
+                TokenType::Semicolon,
+                TokenType::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dot_numbers() {
+        let tokens = Scanner::parse(".5 + .25;");
+        assert_eq!(tokens[0].token_type(), TokenType::Number);
+        assert_eq!(tokens[0].literal(), &Literal::Number(0.5));
+        assert_eq!(tokens[2].literal(), &Literal::Number(0.25));
+
+        let errors = Scanner::scan("1 + 5.;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "Expect digit after '.' in number literal.");
+        assert!(Scanner::scan("5. + 1;").is_err());
+        assert!(Scanner::scan("5.0;").is_ok());
+    }
+
+    #[test]
+    fn test_dot_number_values() {
+        // .5 求值之后和 0.5 一样
+        assert_eq!(eval_ok(".5 + .5 == 1;").into_string(), "true");
+        assert_eq!(eval_ok("[.25][0] * 4;").into_string(), "1");
+        assert_eq!(eval_ok("-.5;").into_string(), "-0.5");
+
+        // 5. 在扫描阶段就报错，不会被当成 5
+        let errors = Scanner::scan("[5.][0];").unwrap_err();
+        assert_eq!(errors[0].msg, "Expect digit after '.' in number literal.");
+    }
+
+    #[test]
+    fn test_unterminated_string_line() {
+        let mut scanner = Scanner::new("1;\n\"abc\ndef\n\nghi");
+        let tokens: Vec<_> = scanner.by_ref().collect();
+
+        assert_eq!(scanner.errors.len(), 1);
+        assert_eq!(scanner.errors[0].msg, "Unterminated String");
+        assert_eq!(scanner.errors[0].line, 2);
+        // 后面的 token 仍然使用真实的行号
+        assert_eq!(tokens.last().unwrap().line(), 5);
+    }
+
+    #[test]
+    fn test_shebang() {
+        let tokens = Scanner::scan("#!/usr/bin/env rlox\n1 + 2;\n").unwrap();
+        assert_eq!(tokens[0].token_type(), TokenType::Number);
+        assert_eq!(tokens[0].line(), 2);
+
+        let errors = Scanner::scan("#!/usr/bin/env rlox\n1;\n#!").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "Unexpected character.");
+        assert_eq!(errors[0].line, 3);
+        assert!(Scanner::scan(" #!/usr/bin/env rlox").is_err());
+    }
+
+    #[test]
+    fn test_recover_from_bad_character() {
+        let clean = Scanner::scan("var x = 1;").unwrap();
+
+        for (prefix, errors) in [("@", 1), ("§", 1), ("@§", 2)] {
+            let source = format!("{prefix}var x = 1;");
+            let mut scanner = Scanner::new(&source);
+            let tokens: Vec<_> = scanner.by_ref().collect();
+            assert_eq!(scanner.errors().len(), errors, "{source:?}");
+            assert!(scanner
+                .errors()
+                .iter()
+                .all(|e| e.msg == "Unexpected character."));
+
+            // 跳过出错的字符之后，后面的 token 与没有出错时完全一样，只是字节偏移量整体后移
+            assert_eq!(tokens.len(), clean.len());
+            for (token, expected) in tokens.iter().zip(&clean) {
+                assert_eq!(token.token_type(), expected.token_type());
+                assert_eq!(token.lexeme(), expected.lexeme());
+                assert_eq!(token.literal(), expected.literal());
+                assert_eq!(token.start(), expected.start() + prefix.len());
+                assert_eq!(&source[token.span()], token.lexeme());
+            }
+        }
+
+        // 非 ASCII 字符不会打乱后面的 token
+        let tokens = Scanner::scan("\"é\" + 1;").unwrap();
+        assert_eq!(tokens[0].literal(), &Literal::String("é".into()));
+        assert_eq!(tokens[1].lexeme(), "+");
+        assert_eq!(tokens[2].literal(), &Literal::Int(1));
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(Scanner::is_complete("1 + 2;"));
+        assert!(Scanner::is_complete(""));
+        assert!(!Scanner::is_complete("[1,\n2\n"));
+        assert!(!Scanner::is_complete("(1 + [2]"));
+        assert!(Scanner::is_complete("(1 + [2]);"));
+        assert!(!Scanner::is_complete("\"abc\ndef"));
+        assert!(!Scanner::is_complete("\"a${ (1\n"));
+        // 字符串和注释中的括号不算
+        assert!(Scanner::is_complete("\"(\"; // ["));
+    }
+
+    #[test]
+    fn test_parse_with_source() {
+        let (tokens, map) = Scanner::parse_with_source("1 +\n  [2][0];").unwrap();
+        let bracket = &tokens[2];
+        assert_eq!(bracket.token_type(), TokenType::LeftBracket);
+
+        let (line, column) = map.offset_to_line_col(bracket.start());
+        assert_eq!((line, column), (2, 3));
+        assert_eq!(map.line_text(line), Some("  [2][0];"));
+
+        // 词法错误不会退出进程，而是交给调用方
+        let errors = Scanner::parse_with_source("1;\n@ \"a").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].msg, "Unexpected character.");
+        assert_eq!(errors[1].line, 2);
+    }
+
+    #[test]
+    fn test_number_at_end_of_source() {
+        let tokens = Scanner::scan("123").unwrap();
+        assert_eq!(tokens[0].literal(), &Literal::Int(123));
+        assert_eq!(tokens[1].token_type(), TokenType::EOF);
+
+        let tokens = Scanner::scan("1 + 2.5").unwrap();
+        assert_eq!(tokens[2].literal(), &Literal::Number(2.5));
+    }
+
+    #[test]
+    fn test_integer_out_of_range() {
+        let tokens = Scanner::scan("9223372036854775807").unwrap();
+        assert_eq!(tokens[0].literal(), &Literal::Int(i64::MAX));
+
+        // 不会悄悄变成不精确的浮点数
+        let errors = Scanner::scan("1 + 9223372036854775808;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "Integer literal out of range.");
+        assert_eq!(errors[0].offset, 4);
+
+        let tokens = Scanner::scan("9223372036854775808.0").unwrap();
+        assert_eq!(tokens[0].literal(), &Literal::Number(9223372036854775808.0));
+    }
+
+    #[test]
+    fn test_max_tokens() {
+        let errors = Scanner::new("1 + 2 + 3;")
+            .with_max_tokens(3)
+            .scan_all()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "Too many tokens (limit 3).");
+
+        // 正好等于上限时不报错，EOF 不计数
+        let tokens = Scanner::new("1 + 2;  ")
+            .with_max_tokens(4)
+            .scan_all()
+            .unwrap();
+        assert_eq!(tokens.len(), 5);
+    }
+
+    #[test]
+    fn test_keep_comments() {
+        let source = "1; // one\n// two\n2;";
+
+        // 默认丢弃注释
+        let tokens = Scanner::scan(source).unwrap();
+        assert!(tokens.iter().all(|t| t.token_type() != TokenType::Comment));
+
+        let tokens = Scanner::new(source)
+            .with_keep_comments(true)
+            .scan_all()
+            .unwrap();
+        let comments: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.token_type() == TokenType::Comment)
+            .collect();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].lexeme(), "// one");
+        assert_eq!(comments[0].literal(), &Literal::String(" one".into()));
+        assert_eq!(comments[0].line(), 1);
+        assert_eq!(&source[comments[0].span()], "// one");
+        assert_eq!(comments[1].lexeme(), "// two");
+        assert_eq!(comments[1].line(), 2);
+        assert_eq!(tokens.len(), 7);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let sources = [
+            "1;\n\"a\nb\";\n// c\n2;",
+            "1;\r\n\"a\r\nb\";\r\n// c\r\n2;",
+            "1;\r\"a\rb\";\r// c\r2;",
+        ];
+        for source in sources {
+            let tokens = Scanner::scan(source).unwrap();
+            let lines: Vec<_> = tokens.iter().map(|t| t.line()).collect();
+            assert_eq!(lines, [1, 1, 3, 3, 5, 5, 5], "{source:?}");
+        }
+    }
 }
@@ -0,0 +1,163 @@
+// 默认的制表符宽度，与大多数终端一致
+pub const TAB_WIDTH: usize = 8;
+
+// 记录源码中每一行的起始偏移量，用于把 token 的偏移量换算成 (行, 列)
+// 偏移量与 Scanner 保持一致，是字节偏移量；列号按字符的显示宽度计算，制表符跳到下一个制表位
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMap {
+    line_starts: Vec<usize>,
+    lines: Vec<String>,
+    tab_width: usize,
+}
+
+impl SourceMap {
+    // 与 Scanner 一致，"\r\n"、"\n" 和单独的 "\r" 都算作换行
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut lines = vec![];
+        let mut line = String::new();
+
+        // 换行符都是单字节的，下一行从 i + 1 开始
+        for (i, c) in source.char_indices() {
+            match c {
+                '\r' if source.as_bytes().get(i + 1) == Some(&b'\n') => (),
+                '\n' | '\r' => {
+                    line_starts.push(i + 1);
+                    lines.push(std::mem::take(&mut line));
+                }
+                c => line.push(c),
+            }
+        }
+        lines.push(line);
+
+        SourceMap {
+            line_starts,
+            lines,
+            tab_width: TAB_WIDTH,
+        }
+    }
+
+    // 制表符宽度由 Scanner 决定，通过 Scanner::source_map 传进来，两者不会不一致
+    #[inline]
+    pub(crate) fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    // 返回的行号和列号都从 1 开始
+    pub fn offset_to_line_col(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+
+        let bytes = offset - self.line_starts[line];
+        let prefix = self.lines.get(line).map(|s| s.as_str()).unwrap_or("");
+        let column = prefix
+            .char_indices()
+            .take_while(|(i, _)| *i < bytes)
+            .fold(0, |column, (_, c)| self.advance_column(column, c));
+
+        // 偏移量超出这一行的文本时（比如换行符本身），按普通字符计算
+        (line + 1, column + bytes.saturating_sub(prefix.len()) + 1)
+    }
+
+    // 把一行源码中的制表符展开成空格，这样输出的 ^ 能和列号对齐
+    pub fn expand_tabs(&self, text: &str) -> String {
+        let mut out = String::new();
+        for c in text.chars() {
+            if c == '\t' {
+                let width = self.advance_column(out.chars().count(), c) - out.chars().count();
+                out.push_str(&" ".repeat(width));
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[inline]
+    fn advance_column(&self, column: usize, c: char) -> usize {
+        if c == '\t' {
+            (column / self.tab_width + 1) * self.tab_width
+        } else {
+            column + 1
+        }
+    }
+
+    // 第 line 行的源码（不含换行符），行号从 1 开始
+    pub fn line_text(&self, line: usize) -> Option<&str> {
+        self.lines.get(line.checked_sub(1)?).map(|s| s.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn test_offset_to_line_col() {
+        let map = SourceMap::new("var a = 1;\nprint a;\n\n  a + 1;");
+
+        assert_eq!(map.offset_to_line_col(0), (1, 1));
+        assert_eq!(map.offset_to_line_col(4), (1, 5));
+        assert_eq!(map.offset_to_line_col(10), (1, 11));
+        assert_eq!(map.offset_to_line_col(11), (2, 1));
+        assert_eq!(map.offset_to_line_col(17), (2, 7));
+        assert_eq!(map.offset_to_line_col(20), (3, 1));
+        assert_eq!(map.offset_to_line_col(23), (4, 3));
+    }
+
+    #[test]
+    fn test_non_ascii_columns() {
+        // 偏移量按字节计算，列号按字符计算
+        let source = "\"é€\" + x;\n😀 y;";
+        let map = SourceMap::new(source);
+        assert_eq!(map.offset_to_line_col(source.find('+').unwrap()), (1, 6));
+        assert_eq!(map.offset_to_line_col(source.find('x').unwrap()), (1, 8));
+        assert_eq!(map.offset_to_line_col(source.find('y').unwrap()), (2, 3));
+        assert_eq!(map.line_text(2), Some("😀 y;"));
+    }
+
+    #[test]
+    fn test_line_text() {
+        let map = SourceMap::new("1 + 2;\r\n\"a\";");
+        assert_eq!(map.line_text(1), Some("1 + 2;"));
+        assert_eq!(map.line_text(2), Some("\"a\";"));
+        assert_eq!(map.line_text(0), None);
+        assert_eq!(map.line_text(3), None);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        for source in ["1;\n2;\n-3;", "1;\r\n2;\r\n-3;", "1;\r2;\r-3;"] {
+            let map = SourceMap::new(source);
+            let offset = source.find('-').unwrap();
+            assert_eq!(map.offset_to_line_col(offset), (3, 1));
+            assert_eq!(map.line_text(2), Some("2;"));
+            assert_eq!(map.line_text(3), Some("-3;"));
+        }
+    }
+
+    #[test]
+    fn test_empty_source() {
+        let map = SourceMap::new("");
+        assert_eq!(map.offset_to_line_col(0), (1, 1));
+        assert_eq!(map.line_text(1), Some(""));
+    }
+
+    #[test]
+    fn test_tab_width() {
+        let source = "1;\n\t-\"a\";\n  \t\t2;";
+        let map = SourceMap::new(source);
+        assert_eq!(map.offset_to_line_col(4), (2, 9));
+        assert_eq!(map.offset_to_line_col(5), (2, 10));
+        assert_eq!(map.offset_to_line_col(14), (3, 17));
+
+        let map = Scanner::new(source).with_tab_width(4).source_map();
+        assert_eq!(map.offset_to_line_col(4), (2, 5));
+        assert_eq!(map.offset_to_line_col(14), (3, 9));
+        assert_eq!(map.expand_tabs("  \t\t2;"), "        2;");
+    }
+}
@@ -0,0 +1,46 @@
+use crate::expression::interpreter::{Interpreter, RuntimeError, Value};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+
+// 测试用的辅助函数：扫描、解析并依次执行源码中的每个表达式，返回最后一个表达式的值。
+// 词法错误或者语法错误直接 panic，它们不是这里要测试的东西。
+fn eval(source: &str) -> Result<Value, RuntimeError> {
+    let tokens = Scanner::scan(source).expect("scan error");
+    let program = Parser::parse_program(tokens).expect("parse error");
+    let interpreter = Interpreter::new();
+
+    let mut value = Value::Nil;
+    for expr in &program {
+        value = interpreter.evaluate(expr)?;
+    }
+    Ok(value)
+}
+
+// 执行成功时返回最后一个表达式的值，出现运行时错误时 panic
+pub fn eval_ok(source: &str) -> Value {
+    match eval(source) {
+        Ok(value) => value,
+        Err(err) => panic!("unexpected runtime error in {source:?}: {err:?}"),
+    }
+}
+
+// 返回执行时遇到的第一个运行时错误，执行成功时 panic
+pub fn eval_err(source: &str) -> RuntimeError {
+    match eval(source) {
+        Ok(value) => panic!("expected a runtime error in {source:?}, got {value:?}"),
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expression::interpreter::RuntimeErrorKind;
+    use crate::testutil::{eval_err, eval_ok};
+
+    #[test]
+    fn test_eval_helpers() {
+        assert_eq!(eval_ok("1+1").into_string(), "2");
+        assert_eq!(eval_ok("1; \"a\" + 2;").into_string(), "a2");
+        assert_eq!(eval_err("1 + nil;").kind, RuntimeErrorKind::TypeMismatch);
+    }
+}
@@ -1,12 +1,17 @@
+use crate::interner::Symbol;
 use std::fmt::{Debug, Display, Formatter};
+use std::ops::{Deref, Range};
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     // Single-character tokens.
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -14,6 +19,11 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Question,
+    Colon,
+    Ampersand,
+    Pipe,
+    Caret,
 
     // One or two character tokens.
     Bang,
@@ -24,11 +34,23 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    LessLess,
+    GreaterGreater,
+    StarStar,
+    QuestionQuestion,
 
     // Literals.
     Identifier,
     String,
     Number,
+    Interpolation, // 插值字符串中 "${" 之前的部分
+
+    // Comments，只有 Scanner 开启 keep_comments 时才会产生
+    Comment,
 
     // Keywords.
     And,
@@ -52,10 +74,148 @@ pub enum TokenType {
     EOF,
 }
 
+// 输出源码中的写法，没有固定写法的 token（字面量、注释、EOF）输出一个描述，用于错误信息
+impl Display for TokenType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use TokenType::*;
+
+        let s = match self {
+            LeftParen => "(",
+            RightParen => ")",
+            LeftBrace => "{",
+            RightBrace => "}",
+            LeftBracket => "[",
+            RightBracket => "]",
+            Comma => ",",
+            Dot => ".",
+            Minus => "-",
+            Plus => "+",
+            Semicolon => ";",
+            Slash => "/",
+            Star => "*",
+            Question => "?",
+            Colon => ":",
+            Ampersand => "&",
+            Pipe => "|",
+            Caret => "^",
+            Bang => "!",
+            BangEqual => "!=",
+            Equal => "=",
+            EqualEqual => "==",
+            Greater => ">",
+            GreaterEqual => ">=",
+            Less => "<",
+            LessEqual => "<=",
+            PlusEqual => "+=",
+            MinusEqual => "-=",
+            StarEqual => "*=",
+            SlashEqual => "/=",
+            LessLess => "<<",
+            GreaterGreater => ">>",
+            StarStar => "**",
+            QuestionQuestion => "??",
+            Identifier => "identifier",
+            String => "string",
+            Number => "number",
+            Interpolation => "interpolated string",
+            Comment => "comment",
+            And => "and",
+            Class => "class",
+            Else => "else",
+            False => "false",
+            Fun => "fun",
+            For => "for",
+            If => "if",
+            Nil => "nil",
+            Or => "or",
+            Print => "print",
+            Return => "return",
+            Super => "super",
+            This => "this",
+            True => "true",
+            Var => "var",
+            While => "while",
+            EOF => "end of file",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// 按照上面的分组给 token 分类，方便语法高亮之类的工具过滤 token
+impl TokenType {
+    pub fn is_keyword(self) -> bool {
+        use TokenType::*;
+        matches!(
+            self,
+            And | Class
+                | Else
+                | False
+                | Fun
+                | For
+                | If
+                | Nil
+                | Or
+                | Print
+                | Return
+                | Super
+                | This
+                | True
+                | Var
+                | While
+        )
+    }
+
+    pub fn is_literal(self) -> bool {
+        use TokenType::*;
+        matches!(self, Identifier | String | Number | Interpolation)
+    }
+
+    // 括号、逗号、点和分号是分隔符，不算运算符
+    pub fn is_operator(self) -> bool {
+        use TokenType::*;
+        matches!(
+            self,
+            Minus
+                | Plus
+                | Slash
+                | Star
+                | Question
+                | Colon
+                | Ampersand
+                | Pipe
+                | Caret
+                | Bang
+                | BangEqual
+                | Equal
+                | EqualEqual
+                | Greater
+                | GreaterEqual
+                | Less
+                | LessEqual
+                | PlusEqual
+                | MinusEqual
+                | StarEqual
+                | SlashEqual
+                | LessLess
+                | GreaterGreater
+                | StarStar
+                | QuestionQuestion
+        )
+    }
+
+    #[inline]
+    pub fn is_eof(self) -> bool {
+        self == TokenType::EOF
+    }
+}
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Literal {
-    String(String), // string literal
-    Number(f64),    // 所有的number内部使用f64储存
+    String(Symbol), // string literal，与 lexeme 一样被驻留
+    Int(i64),       // 没有小数点的整数
+    Number(f64),    // 带小数点的number使用f64储存
+    Bool(bool),     // true 和 false
     None,           // 用于占位，表示该Token没有Literal
 }
 
@@ -65,9 +225,15 @@ impl Debug for Literal {
             Literal::String(s) => {
                 write!(f, "string:\"{}\"", s)
             }
+            Literal::Int(i) => {
+                write!(f, "int:\"{}\"", i)
+            }
             Literal::Number(fl) => {
                 write!(f, "number:\"{}\"", fl)
             }
+            Literal::Bool(b) => {
+                write!(f, "bool:\"{}\"", b)
+            }
             Literal::None => {
                 write!(f, "None")
             }
@@ -81,9 +247,15 @@ impl Display for Literal {
             Literal::String(s) => {
                 write!(f, "{}", s)
             }
+            Literal::Int(i) => {
+                write!(f, "{}", i)
+            }
             Literal::Number(fl) => {
                 write!(f, "{}", fl)
             }
+            Literal::Bool(b) => {
+                write!(f, "{}", b)
+            }
             Literal::None => {
                 write!(f, "None")
             }
@@ -92,25 +264,33 @@ impl Display for Literal {
 }
 
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     token_type: TokenType, // token的类型
-    lexeme: String,        // token的源代码中的表示
+    lexeme: Symbol,        // token的源代码中的表示，由 Scanner 的 Interner 驻留
     literal: Literal, // 当token为String或者Number时，这里记录String或者Number的具体内容，其他的为Null
     line: usize,      // token在源码的第几行
+    start: usize,     // token在源码中的起始字节偏移量
+    len: usize,       // token在源码中占用的字节数，&source[token.span()] 就是 lexeme
 }
 
 impl Token {
     pub fn new(
         token_type: TokenType,
-        lexeme: impl ToString,
+        lexeme: impl Into<Symbol>,
         literal: Literal,
         line: usize,
+        start: usize,
     ) -> Self {
+        let lexeme = lexeme.into();
+        // Scanner 中 lexeme 就是 source[start..current]，所以长度就是 lexeme 的字节数
         Token {
             token_type,
-            lexeme: lexeme.to_string(),
+            len: lexeme.as_str().len(),
+            lexeme,
             literal,
             line,
+            start,
         }
     }
 
@@ -119,6 +299,10 @@ impl Token {
     }
 
     pub fn lexeme(&self) -> &str {
+        self.lexeme.as_str()
+    }
+
+    pub fn symbol(&self) -> &Symbol {
         &self.lexeme
     }
 
@@ -129,6 +313,31 @@ impl Token {
     pub fn line(&self) -> usize {
         self.line
     }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    // token 在源码中的范围，可以用来取出对应的源码片段
+    #[inline]
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end()
+    }
 }
 
 impl Debug for Token {
@@ -154,7 +363,7 @@ impl Display for Token {
                 write!(f, "{}", self.literal)?;
             }
             _ => {
-                write!(f, "{}", self.lexeme)?;
+                write!(f, "{}", self.lexeme())?;
             }
         }
         Ok(())
@@ -170,6 +379,39 @@ impl From<Vec<Token>> for TokenStream {
     }
 }
 
+// 像切片一样使用：可以直接索引、取长度、调用 iter()
+impl Deref for TokenStream {
+    type Target = [Token];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl IntoIterator for TokenStream {
+    type Item = Token;
+    type IntoIter = std::vec::IntoIter<Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TokenStream {
+    type Item = &'a Token;
+    type IntoIter = std::slice::Iter<'a, Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl From<TokenStream> for Vec<Token> {
+    fn from(value: TokenStream) -> Self {
+        value.0
+    }
+}
+
 impl Display for TokenStream {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut tokens = self.0.iter();
@@ -186,16 +428,107 @@ impl Display for TokenStream {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scanner::Scanner;
 
     #[test]
     fn test() {
         let token = Token::new(
             TokenType::String,
             "String",
-            Literal::String("Hello World".to_string()),
+            Literal::String("Hello World".into()),
             12,
+            0,
         );
         println!("{token:?}");
         println!("{token}");
     }
+
+    #[test]
+    fn test_token_type_groups() {
+        let tokens = Scanner::parse("(a + 1) >= \"s\" and nil;");
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type()).collect();
+        let keywords: Vec<_> = types.iter().filter(|t| t.is_keyword()).collect();
+        let literals: Vec<_> = types.iter().filter(|t| t.is_literal()).collect();
+        let operators: Vec<_> = types.iter().filter(|t| t.is_operator()).collect();
+
+        assert_eq!(keywords, [&TokenType::And, &TokenType::Nil]);
+        assert_eq!(
+            literals,
+            [
+                &TokenType::Identifier,
+                &TokenType::Number,
+                &TokenType::String
+            ]
+        );
+        assert_eq!(operators, [&TokenType::Plus, &TokenType::GreaterEqual]);
+        assert!(types.last().unwrap().is_eof());
+
+        for punctuation in [TokenType::LeftParen, TokenType::Comma, TokenType::Semicolon] {
+            assert!(!punctuation.is_operator());
+            assert!(!punctuation.is_keyword());
+            assert!(!punctuation.is_literal());
+            assert!(!punctuation.is_eof());
+        }
+    }
+
+    #[test]
+    fn test_span() {
+        let source = "1 + count * \"ab\";";
+        let tokens = Scanner::parse(source);
+
+        let identifier = &tokens[2];
+        assert_eq!(identifier.token_type(), TokenType::Identifier);
+        assert_eq!(identifier.span(), 4..9);
+        assert_eq!(&source[identifier.span()], "count");
+        assert_eq!(&source[tokens[4].span()], "\"ab\"");
+
+        let eof = tokens.last().unwrap();
+        assert!(eof.is_empty());
+        assert_eq!(eof.end(), source.len());
+
+        // 偏移量是字节偏移量，前面有非 ASCII 字符时仍然可以直接切片
+        let source = "\"héllo→\" + naive;";
+        let tokens = Scanner::parse(source);
+        assert_eq!(&source[tokens[0].span()], "\"héllo→\"");
+        assert_eq!(tokens[2].span(), 14..19);
+        assert_eq!(&source[tokens[2].span()], "naive");
+        assert_eq!(tokens.last().unwrap().end(), source.len());
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let tokens: TokenStream = Scanner::parse("1 + 2;").into();
+
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[1].token_type(), TokenType::Plus);
+        assert_eq!(tokens.last().map(Token::token_type), Some(TokenType::EOF));
+
+        let mut count = 0;
+        for token in &tokens {
+            assert!(!token.token_type().is_keyword());
+            count += 1;
+        }
+        assert_eq!(count, tokens.len());
+
+        let lexemes: Vec<_> = tokens.into_iter().map(|t| t.lexeme().to_string()).collect();
+        assert_eq!(lexemes, ["1", "+", "2", ";", ""]);
+    }
+
+    #[test]
+    fn test_token_type_display() {
+        assert_eq!(TokenType::Plus.to_string(), "+");
+        assert_eq!(TokenType::EqualEqual.to_string(), "==");
+        assert_eq!(TokenType::QuestionQuestion.to_string(), "??");
+        assert_eq!(TokenType::LeftBrace.to_string(), "{");
+        assert_eq!(TokenType::While.to_string(), "while");
+        assert_eq!(TokenType::Identifier.to_string(), "identifier");
+        assert_eq!(TokenType::EOF.to_string(), "end of file");
+
+        // 有固定写法的 token 输出的就是 scanner 读入的 lexeme
+        for token in Scanner::parse("( ) { } [ ] , . - + ; / * ? : & | ^ ! != = == > >= < <= += -= *= /= << >> ** ?? and class else false fun for if nil or print return super this true var while") {
+            if !token.token_type().is_eof() {
+                assert_eq!(token.token_type().to_string(), token.lexeme());
+            }
+        }
+    }
 }
@@ -0,0 +1,529 @@
+use crate::expression::interpreter::{RuntimeError, RuntimeErrorKind, Value};
+use crate::expression::{ExprLiteral, ExprVisitor, Expression};
+use crate::token::{Literal, Token, TokenType};
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+// 栈式虚拟机的指令，操作数都从栈顶取，结果压回栈顶
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+    Constant(usize), // 把常量表中的第 n 个常量压栈
+    Negate,
+    Not,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    List(usize),         // 把栈顶 n 个值组成一个列表
+    Map(usize),          // 把栈顶 n 对 key、value 组成一个 map
+    Index,               // object index -> value
+    IndexSet,            // object index value -> value
+    Dup2,                // a b -> a b a b
+    Interpolate(usize),  // 把栈顶 n 个值转成字符串之后拼接起来
+    Jump(usize),         // 跳转到第 n 条指令
+    JumpIfFalse(usize),  // 栈顶为假时跳转，不弹出栈顶
+    JumpIfNotNil(usize), // 栈顶不是 nil 时跳转，不弹出栈顶
+    Pop,
+    Print,
+    Unknown, // AST 中出现了无法求值的运算符，运行到这里时报错
+    Return,  // 结束执行，返回栈顶的值（栈为空时返回 nil）
+}
+
+// 编译之后的指令序列，tokens[i] 是第 i 条指令对应的 token，运行时出错时用来定位
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    code: Vec<OpCode>,
+    tokens: Vec<Token>,
+    constants: Vec<Value>,
+}
+
+impl Chunk {
+    #[inline]
+    fn emit(&mut self, op: OpCode, token: &Token) -> usize {
+        self.code.push(op);
+        self.tokens.push(token.clone());
+        self.code.len() - 1
+    }
+
+    // 跳转目标在编译完后面的代码之后才知道，先占位再回填
+    #[inline]
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            OpCode::Jump(to) | OpCode::JumpIfFalse(to) | OpCode::JumpIfNotNil(to) => *to = target,
+            op => unreachable!("{op:?} is not a jump"),
+        }
+    }
+
+    #[inline]
+    fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+}
+
+// 反汇编，每行一条指令，例如：0000 Constant(0)  ; 1
+impl Display for Chunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, op) in self.code.iter().enumerate() {
+            match op {
+                OpCode::Constant(n) => writeln!(f, "{i:04} {op:?}  ; {}", self.constants[*n])?,
+                op => writeln!(f, "{i:04} {op:?}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// 把 AST 编译成 Chunk，与 SourcePrinter 一样通过 visitor 遍历
+pub struct Compiler {
+    chunk: RefCell<Chunk>,
+}
+
+impl Compiler {
+    // 编译整个程序，每个表达式语句的结果都会被打印出来
+    pub fn compile(program: &[Expression]) -> Chunk {
+        let compiler = Compiler::new();
+        for expr in program {
+            expr.accept(&compiler);
+            compiler.emit(OpCode::Print, &Self::no_token());
+        }
+        compiler.finish()
+    }
+
+    // 编译单个表达式，运行结果就是这个表达式的值
+    pub fn compile_expression(expr: &Expression) -> Chunk {
+        let compiler = Compiler::new();
+        expr.accept(&compiler);
+        compiler.finish()
+    }
+
+    fn new() -> Self {
+        Compiler {
+            chunk: RefCell::new(Chunk::default()),
+        }
+    }
+
+    fn finish(self) -> Chunk {
+        self.emit(OpCode::Return, &Self::no_token());
+        self.chunk.into_inner()
+    }
+
+    #[inline]
+    fn emit(&self, op: OpCode, token: &Token) -> usize {
+        self.chunk.borrow_mut().emit(op, token)
+    }
+
+    #[inline]
+    fn patch_jump(&self, at: usize) {
+        self.chunk.borrow_mut().patch_jump(at);
+    }
+
+    #[inline]
+    fn no_token() -> Token {
+        Token::new(TokenType::EOF, "", Literal::None, 0, 0)
+    }
+
+    fn constant(&self, value: Value, token: &Token) {
+        let n = self.chunk.borrow_mut().add_constant(value);
+        self.emit(OpCode::Constant(n), token);
+    }
+
+    // 二元运算符对应的指令，二元表达式和复合赋值共用
+    fn binary_op(operator: &Token) -> OpCode {
+        match operator.token_type() {
+            TokenType::Minus => OpCode::Subtract,
+            TokenType::Plus => OpCode::Add,
+            TokenType::Slash => OpCode::Divide,
+            TokenType::Star => OpCode::Multiply,
+            TokenType::StarStar => OpCode::Power,
+            TokenType::Greater => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::Less => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::BangEqual => OpCode::NotEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::Ampersand => OpCode::BitAnd,
+            TokenType::Pipe => OpCode::BitOr,
+            TokenType::Caret => OpCode::BitXor,
+            TokenType::LessLess => OpCode::ShiftLeft,
+            TokenType::GreaterGreater => OpCode::ShiftRight,
+            // 与解释器一样，未知的运算符留到运行时再报错
+            _ => OpCode::Unknown,
+        }
+    }
+}
+
+impl ExprVisitor<()> for Compiler {
+    fn visit_binary(&self, left: &Box<Expression>, operator: &Token, right: &Box<Expression>) {
+        left.accept(self);
+        right.accept(self);
+
+        self.emit(Self::binary_op(operator), operator);
+    }
+
+    // 短路求值：左操作数已经能决定结果时跳过右操作数
+    fn visit_logical(&self, left: &Box<Expression>, operator: &Token, right: &Box<Expression>) {
+        left.accept(self);
+
+        let end = match operator.token_type() {
+            TokenType::Or => {
+                let else_jump = self.emit(OpCode::JumpIfFalse(0), operator);
+                let end = self.emit(OpCode::Jump(0), operator);
+                self.patch_jump(else_jump);
+                end
+            }
+            TokenType::And => self.emit(OpCode::JumpIfFalse(0), operator),
+            TokenType::QuestionQuestion => self.emit(OpCode::JumpIfNotNil(0), operator),
+            // 与解释器一样，求出左操作数之后报错
+            _ => {
+                self.emit(OpCode::Unknown, operator);
+                return;
+            }
+        };
+
+        self.emit(OpCode::Pop, operator);
+        right.accept(self);
+        self.patch_jump(end);
+    }
+
+    fn visit_literal(&self, value: &ExprLiteral) {
+        self.constant(value.into(), &Self::no_token());
+    }
+
+    fn visit_grouping(&self, expr: &Box<Expression>) {
+        expr.accept(self);
+    }
+
+    fn visit_unary(&self, operator: &Token, right: &Box<Expression>) {
+        right.accept(self);
+        match operator.token_type() {
+            TokenType::Minus => self.emit(OpCode::Negate, operator),
+            TokenType::Bang => self.emit(OpCode::Not, operator),
+            _ => self.emit(OpCode::Unknown, operator),
+        };
+    }
+
+    fn visit_ternary(
+        &self,
+        condition: &Box<Expression>,
+        then_expr: &Box<Expression>,
+        else_expr: &Box<Expression>,
+    ) {
+        let token = Self::no_token();
+        condition.accept(self);
+        let else_jump = self.emit(OpCode::JumpIfFalse(0), &token);
+        self.emit(OpCode::Pop, &token);
+        then_expr.accept(self);
+        let end = self.emit(OpCode::Jump(0), &token);
+
+        self.patch_jump(else_jump);
+        self.emit(OpCode::Pop, &token);
+        else_expr.accept(self);
+        self.patch_jump(end);
+    }
+
+    fn visit_array(&self, elements: &Vec<Expression>) {
+        for element in elements {
+            element.accept(self);
+        }
+        self.emit(OpCode::List(elements.len()), &Self::no_token());
+    }
+
+    fn visit_map(&self, brace: &Token, entries: &Vec<(Expression, Expression)>) {
+        for (key, value) in entries {
+            key.accept(self);
+            value.accept(self);
+        }
+        self.emit(OpCode::Map(entries.len()), brace);
+    }
+
+    fn visit_index(&self, object: &Box<Expression>, bracket: &Token, index: &Box<Expression>) {
+        object.accept(self);
+        index.accept(self);
+        self.emit(OpCode::Index, bracket);
+    }
+
+    fn visit_index_set(
+        &self,
+        object: &Box<Expression>,
+        bracket: &Token,
+        index: &Box<Expression>,
+        operator: &Option<Token>,
+        value: &Box<Expression>,
+    ) {
+        object.accept(self);
+        index.accept(self);
+        if let Some(operator) = operator {
+            // 复制一份 object 和 index 用来读取旧值，两者都只求值一次
+            self.emit(OpCode::Dup2, bracket);
+            self.emit(OpCode::Index, bracket);
+            value.accept(self);
+            self.emit(Self::binary_op(operator), operator);
+        } else {
+            value.accept(self);
+        }
+        self.emit(OpCode::IndexSet, bracket);
+    }
+
+    fn visit_interpolation(&self, parts: &Vec<Expression>) {
+        for part in parts {
+            part.accept(self);
+        }
+        self.emit(OpCode::Interpolate(parts.len()), &Self::no_token());
+    }
+}
+
+#[derive(Default)]
+pub struct VM {
+    stack: Vec<Value>,
+}
+
+impl VM {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn run(&mut self, chunk: &Chunk) -> Result<Value, RuntimeError> {
+        let mut ip = 0;
+
+        loop {
+            let op = chunk.code[ip];
+            let token = &chunk.tokens[ip];
+            ip += 1;
+
+            match op {
+                OpCode::Constant(n) => self.stack.push(chunk.constants[n].clone()),
+                OpCode::Negate => {
+                    let val = self.pop().try_neg(token)?;
+                    self.stack.push(val);
+                }
+                OpCode::Not => {
+                    let val = self.pop().try_not(token)?;
+                    self.stack.push(val);
+                }
+                OpCode::Add => self.binary(token, Value::try_add)?,
+                OpCode::Subtract => self.binary(token, Value::try_sub)?,
+                OpCode::Multiply => self.binary(token, Value::try_mul)?,
+                OpCode::Divide => self.binary(token, Value::try_div)?,
+                OpCode::Power => self.binary(token, Value::try_pow)?,
+                OpCode::Greater => self.binary(token, Value::try_gt)?,
+                OpCode::GreaterEqual => self.binary(token, Value::try_gte)?,
+                OpCode::Less => self.binary(token, Value::try_lt)?,
+                OpCode::LessEqual => self.binary(token, Value::try_lte)?,
+                OpCode::Equal => self.binary(token, Value::try_eq)?,
+                OpCode::NotEqual => self.binary(token, Value::try_neq)?,
+                OpCode::BitAnd => self.binary(token, Value::try_bit_and)?,
+                OpCode::BitOr => self.binary(token, Value::try_bit_or)?,
+                OpCode::BitXor => self.binary(token, Value::try_bit_xor)?,
+                OpCode::ShiftLeft => self.binary(token, Value::try_shl)?,
+                OpCode::ShiftRight => self.binary(token, Value::try_shr)?,
+                OpCode::List(n) => {
+                    let values = self.stack.split_off(self.stack.len() - n);
+                    self.stack.push(Value::List(Rc::new(RefCell::new(values))));
+                }
+                OpCode::Map(n) => {
+                    let values = self.stack.split_off(self.stack.len() - n * 2);
+                    self.stack.push(Value::map_from(values, token)?);
+                }
+                OpCode::Index => {
+                    let index = self.pop();
+                    let val = self.pop().index_get(index, token)?;
+                    self.stack.push(val);
+                }
+                OpCode::IndexSet => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let val = self.pop().index_set(index, value, token)?;
+                    self.stack.push(val);
+                }
+                OpCode::Dup2 => {
+                    let top = self.stack.len() - 2;
+                    self.stack.extend_from_within(top..);
+                }
+                OpCode::Interpolate(n) => {
+                    let parts = self.stack.split_off(self.stack.len() - n);
+                    let s: String = parts.into_iter().map(Value::into_string).collect();
+                    self.stack.push(Value::Str(s));
+                }
+                OpCode::Jump(to) => ip = to,
+                OpCode::JumpIfFalse(to) => {
+                    if !self.peek().is_truthy() {
+                        ip = to;
+                    }
+                }
+                OpCode::JumpIfNotNil(to) => {
+                    if !matches!(self.peek(), Value::Nil) {
+                        ip = to;
+                    }
+                }
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::Print => println!("{}", self.pop().into_string()),
+                OpCode::Unknown => {
+                    return Err(RuntimeError {
+                        kind: RuntimeErrorKind::UnknownOperator,
+                        msg: "Unknown operator",
+                        token: token.clone(),
+                    })
+                }
+                OpCode::Return => return Ok(self.stack.pop().unwrap_or(Value::Nil)),
+            }
+        }
+    }
+
+    #[inline]
+    fn binary(
+        &mut self,
+        token: &Token,
+        op: fn(Value, Value, &Token) -> Result<Value, RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        let right = self.pop();
+        let left = self.pop();
+        self.stack.push(op(left, right, token)?);
+        Ok(())
+    }
+
+    // 编译器保证了栈的平衡，这里为空说明 Chunk 本身有问题
+    #[inline]
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("vm stack underflow")
+    }
+
+    #[inline]
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("vm stack underflow")
+    }
+
+    // 给 main 使用：编译并运行整个程序，打印每个表达式语句的结果
+    pub fn interpret(program: &[Expression]) -> Result<(), RuntimeError> {
+        let chunk = Compiler::compile(program);
+        VM::new().run(&chunk).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expression::interpreter::{Interpreter, RuntimeErrorKind};
+    use crate::expression::{ExprLiteral, Expression};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::token::{Literal, Token, TokenType};
+    use crate::vm::{Compiler, OpCode, VM};
+
+    // VM 和解释器对同一段代码应该得到相同的结果（或者都报错）
+    fn assert_same(source: &str) {
+        let program = Parser::parse(Scanner::parse(source));
+        for expr in &program {
+            let expected = expr.accept(&Interpreter::new());
+            let actual = VM::new().run(&Compiler::compile_expression(expr));
+
+            match (expected, actual) {
+                (Ok(expected), Ok(actual)) => {
+                    assert_eq!(actual.to_string(), expected.to_string(), "{source}")
+                }
+                (Err(expected), Err(actual)) => {
+                    assert_eq!(actual.kind, expected.kind, "{source}");
+                    assert_eq!(actual.msg, expected.msg, "{source}");
+                }
+                (expected, actual) => panic!("{source}: {expected:?} != {actual:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_same("1 + 2 * 3; (1 + 2) * 3; 1 - 2 - 3; 7 / 2; 2 ** 3 ** 2;");
+        assert_same("-(1 + 2.5); --3; 4 ** 0.5; 1 + 1 * 2 - 3 / 4;");
+        assert_same("6 & 3; 6 | 3; 6 ^ 3; 1 << 4; -16 >> 2;");
+        assert_same("1 < 2; 2 <= 1; \"a\" > \"b\"; 1 == 1.0; nil != false; !0;");
+        assert_same("9223372036854775807 + 1; 1 / 0; -\"a\"; 1 + true; 1 << 64;");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_same("true ? 1 : 2; nil ? 1 : 0 ? 2 : 3;");
+        assert_same("1 or 2; nil or \"a\"; 0 and 1; 1 and 2; nil and -nil;");
+        assert_same("false or nil or 3;");
+        assert_same("nil ?? 5; false ?? 5; 0 ?? -nil; nil ?? nil ?? 1; nil ?? false or 2;");
+    }
+
+    #[test]
+    fn test_lists_and_strings() {
+        assert_same("[1, [2, 3]][1][0]; [1, 2] + [3]; [1][0] += 2; [1][1];");
+        assert_same("{\"a\": 1, 2: [3]}; {1: 2}[1.0]; {}[\"a\"]; {[]: 1}; {1: 2}[1] = 3;");
+        assert_same("\"${1} + ${2} = ${1 + 2}\"; \"a\" + \"b\";");
+        assert_same("[\"a\"][0] += \"b\"; [1][[0][0]] *= 3; [nil][0] -= 1; [1][0] /= 0;");
+
+        // 复合赋值只编译一次对象和下标
+        let program = Parser::parse(Scanner::parse("[1][0] += 2;"));
+        let chunk = Compiler::compile_expression(&program[0]);
+        assert_eq!(
+            chunk
+                .code
+                .iter()
+                .filter(|op| **op == OpCode::List(1))
+                .count(),
+            1
+        );
+        assert!(chunk.code.contains(&OpCode::Dup2));
+    }
+
+    #[test]
+    fn test_unknown_operator() {
+        let number = |n: f64| {
+            Box::new(Expression::Literal {
+                value: ExprLiteral::Number(n),
+            })
+        };
+        let comma = Token::new(TokenType::Comma, ",", Literal::None, 3, 0);
+        let logical = Expression::Logical {
+            left: number(1_f64),
+            operator: comma,
+            right: number(2_f64),
+        };
+
+        let err = VM::new()
+            .run(&Compiler::compile_expression(&logical))
+            .unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::UnknownOperator);
+        assert_eq!(err.line(), 3);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = Parser::parse(Scanner::parse("1 + 2;"));
+        let chunk = Compiler::compile_expression(&program[0]);
+
+        assert_eq!(chunk.code[2], OpCode::Add);
+        assert_eq!(
+            chunk.to_string(),
+            "0000 Constant(0)  ; 1\n0001 Constant(1)  ; 2\n0002 Add\n0003 Return\n"
+        );
+    }
+}
@@ -0,0 +1,17 @@
+// 以下游 crate 的身份使用 testutil 提供的辅助函数
+use rlox::expression::interpreter::RuntimeErrorKind;
+use rlox::testutil::{eval_err, eval_ok};
+
+#[test]
+fn test_eval_ok() {
+    assert_eq!(eval_ok("1+1").into_string(), "2");
+    assert_eq!(eval_ok("[1, 2][1] * 3;").into_string(), "6");
+    assert_eq!(eval_ok("\"a${1 + 1}\";").into_string(), "a2");
+    assert_eq!(eval_ok("nil;").into_string(), "");
+}
+
+#[test]
+fn test_eval_err() {
+    assert_eq!(eval_err("1 + nil;").kind, RuntimeErrorKind::TypeMismatch);
+    assert_eq!(eval_err("[1][2];").kind, RuntimeErrorKind::IndexOutOfRange);
+}